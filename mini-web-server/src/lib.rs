@@ -5,25 +5,78 @@
 //!
 //! For example to run the server with 10 worker threads listening at port 8787, run the following command -
 //! ./mini-web-server 10 8787
+//!
+//! An optional third argument is a directory to serve files from instead, and a fourth `--listings` lists
+//! the contents of directories without an index file -
+//! ./mini-web-server 10 8787 ./public --listings
 
 use std::{
+    collections::HashMap,
     error::Error,
     fs,
-    io::{prelude::*, BufReader},
-    net::{TcpListener, TcpStream},
-    sync::{mpsc, Arc, Mutex},
+    io::{self, prelude::*, BufReader, BufWriter},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+pub mod request;
+pub mod response;
+pub mod router;
+pub mod static_files;
+
+pub use request::{ParseError, Request, Version};
+pub use response::Response;
+pub use router::{Handler, Params, Router};
+pub use static_files::StaticFiles;
+
 const THREAD_SIZE: usize = 4;
 const PORT: usize = 7878;
+const KEEP_ALIVE: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_REQUESTS: usize = 100;
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
 pub struct Config {
     pub thread_size: usize,
     pub port: usize,
+    /// Directory to serve files from, instead of the welcome page
+    pub root: Option<PathBuf>,
+    /// Whether to list directories without an index file under `root`
+    pub listings: bool,
+    /// How long an idle connection is kept open waiting for its next request
+    /// to start. Each open connection ties up a worker thread for up to this long.
+    pub keep_alive: Duration,
+    /// How long a client may take between reads to send the rest of a request
+    /// it has started, before getting a `408 Request Timeout`, and how long
+    /// writing a response may wait for the client to read it
+    pub request_timeout: Duration,
+    /// How many requests a connection may make before the server closes it
+    pub max_requests: usize,
+    /// How long to wait on shutdown for requests being handled to finish
+    pub shutdown_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            thread_size: THREAD_SIZE,
+            port: PORT,
+            root: None,
+            listings: false,
+            keep_alive: KEEP_ALIVE,
+            request_timeout: REQUEST_TIMEOUT,
+            max_requests: MAX_REQUESTS,
+            shutdown_timeout: SHUTDOWN_TIMEOUT,
+        }
+    }
 }
 
 impl Config {
@@ -60,7 +113,21 @@ impl Config {
             }
         };
 
-        Ok(Config { thread_size, port })
+        let root = args.next().map(PathBuf::from);
+
+        let listings = match args.next().as_deref() {
+            Some("--listings") => true,
+            Some(_) => return Err("The only option after the document root is --listings."),
+            None => false,
+        };
+
+        Ok(Config {
+            thread_size,
+            port,
+            root,
+            listings,
+            ..Config::default()
+        })
     }
 }
 
@@ -137,6 +204,32 @@ impl ThreadPool {
         let job = Box::new(f);
         self.sender.as_ref().unwrap().send(job).unwrap();
     }
+
+    /// Let the workers finish the jobs already sent, waiting at most timeout
+    /// for them. Returns false if some were still busy, which are left running.
+    pub fn shutdown(mut self, timeout: Duration) -> bool {
+        //as in drop, closing the channel ends each worker's loop once the queue is empty
+        drop(self.sender.take());
+
+        let deadline = Instant::now() + timeout;
+        while !self.worker_threads.iter().all(|worker| {
+            worker
+                .thread
+                .as_ref()
+                .is_none_or(|thread| thread.is_finished())
+        }) {
+            if Instant::now() >= deadline {
+                //detach the busy workers so drop doesn't wait for them
+                for worker in &mut self.worker_threads {
+                    worker.thread.take();
+                }
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        true
+    }
 }
 
 impl Drop for ThreadPool {
@@ -154,49 +247,527 @@ impl Drop for ThreadPool {
     }
 }
 
+/// Serve the files under the configured root, or if there isn't one the
+/// welcome page at `/` and, after a five second delay, at `/sleep`, until the
+/// process gets SIGINT (Ctrl-C) or SIGTERM. Then shut down gracefully.
+///
+/// This installs the process's signal handler, which can only be done once.
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    //listen for tcp connections with TcpListner and bind to a port
-    let address = format!("127.0.0.1:{}", config.port);
-    let listener = TcpListener::bind(address)?;
-    let thread_pool = ThreadPool::new(config.thread_size);
+    let router = match &config.root {
+        Some(root) => {
+            let files = StaticFiles::new(root)
+                .map_err(|err| format!("Can't serve {}: {err}", root.display()))?
+                .listings(config.listings);
+            Router::new().get("/*path", move |request, params| {
+                files.serve(request, &params["path"])
+            })
+        }
+        None => Router::new()
+            .get("/", |_, _| html_file(200, "welcome.html"))
+            .get("/sleep", |_, _| {
+                thread::sleep(Duration::from_secs(5));
+                html_file(200, "welcome.html")
+            })
+            .not_found(|_, _| html_file(404, "error.html")),
+    };
+
+    let server = Server::bind(config, router)?;
+    let handle = server.handle();
+    ctrlc::set_handler(move || {
+        println!("Shutting down...");
+        handle.shutdown();
+    })?;
+
+    server.run()?;
+    Ok(())
+}
 
-    //iterate through sequence of streams
-    for stream in listener.incoming() {
-        let stream = stream?;
+/// Serve requests with router until the process exits. It leaves signals
+/// alone; to shut down gracefully, run a `Server` and stop it with its handle.
+pub fn serve(config: Config, router: Router) -> Result<(), Box<dyn Error>> {
+    Server::bind(config, router)?.run()?;
+    Ok(())
+}
+
+/// A server bound to its port, which answers requests with a router once run
+///
+/// # Examples
+///
+/// ```
+/// use mini_web_server::{Config, Response, Router, Server};
+/// use std::{io::prelude::*, net::TcpStream, thread};
+///
+/// let router = Router::new().get("/", |_, _| Response::new(200).body("hello"));
+/// //port 0 picks any free port
+/// let server = Server::bind(Config { port: 0, ..Config::default() }, router).unwrap();
+/// let (address, handle) = (server.local_addr(), server.handle());
+/// let running = thread::spawn(move || server.run());
+///
+/// let mut stream = TcpStream::connect(address).unwrap();
+/// stream.write_all(b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n").unwrap();
+/// let mut response = String::new();
+/// stream.read_to_string(&mut response).unwrap();
+/// assert!(response.ends_with("hello"));
+///
+/// handle.shutdown();
+/// running.join().unwrap().unwrap();
+/// ```
+pub struct Server {
+    listener: TcpListener,
+    router: Arc<Router>,
+    config: Config,
+    shared: Arc<Shared>,
+}
+
+/// What a running server shares with its handles and connections
+struct Shared {
+    address: SocketAddr,
+    shutting_down: AtomicBool,
+    //the open connections, so shutdown can close those waiting for requests
+    connections: Mutex<HashMap<usize, Open>>,
+}
+
+/// An open connection as the server keeps track of it
+struct Open {
+    stream: TcpStream,
+    /// Whether it's between requests, rather than yet to start or busy with one
+    idle: bool,
+}
 
-        thread_pool.execute(|| {
-            handle_connection(stream);
+impl Server {
+    /// Listen on the configured port of 127.0.0.1
+    pub fn bind(config: Config, router: Router) -> io::Result<Server> {
+        //listen for tcp connections with TcpListner and bind to a port
+        let listener = TcpListener::bind(format!("127.0.0.1:{}", config.port))?;
+        let shared = Arc::new(Shared {
+            address: listener.local_addr()?,
+            shutting_down: AtomicBool::new(false),
+            connections: Mutex::new(HashMap::new()),
         });
+
+        Ok(Server {
+            listener,
+            //share the router between worker threads
+            router: Arc::new(router),
+            config,
+            shared,
+        })
     }
 
-    Ok(())
+    /// The address the server is listening on, useful when bound to port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.shared.address
+    }
+
+    /// A handle that shuts the server down, from any thread
+    pub fn handle(&self) -> ServerHandle {
+        ServerHandle {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// Answer connections until shut down by a handle. Then stop accepting,
+    /// close connections waiting between requests, give the rest up to the
+    /// shutdown timeout to answer the request they're on or, if they haven't
+    /// started yet, their first, and return. Each gets `Connection: close`.
+    pub fn run(self) -> io::Result<()> {
+        let thread_pool = ThreadPool::new(self.config.thread_size);
+        let limits = Limits {
+            keep_alive: self.config.keep_alive,
+            request_timeout: self.config.request_timeout,
+            max_requests: self.config.max_requests,
+        };
+
+        //iterate through sequence of streams
+        for (id, stream) in self.listener.incoming().enumerate() {
+            if self.shared.shutting_down.load(Ordering::SeqCst) {
+                break;
+            }
+            //failing to accept one connection, say for lack of file descriptors, shouldn't stop the rest
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    println!("Failed to accept connection: {err}");
+                    continue;
+                }
+            };
+            let open = Open {
+                stream: stream.try_clone()?,
+                idle: false,
+            };
+            self.shared.connections.lock().unwrap().insert(id, open);
+
+            let router = Arc::clone(&self.router);
+            let shared = Arc::clone(&self.shared);
+            thread_pool.execute(move || {
+                let served = handle_connection(stream, &router, limits, &shared, id);
+                if let Err(err) = served {
+                    println!("Connection closed: {err}");
+                }
+                shared.connections.lock().unwrap().remove(&id);
+            });
+        }
+        //close the port, so clients connecting from now on are refused rather
+        //than left waiting in the backlog for an answer that never comes
+        drop(self.listener);
+
+        //connections waiting for their next request see the end of the stream and close, while
+        //those yet to start or busy with a request still get to answer it
+        self.shared.connections.lock().unwrap().retain(|_, open| {
+            if open.idle {
+                let _ = open.stream.shutdown(Shutdown::Read);
+            }
+            !open.idle
+        });
+
+        if !thread_pool.shutdown(self.config.shutdown_timeout) {
+            println!(
+                "Requests still running after {:?}; shutting down anyway.",
+                self.config.shutdown_timeout
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Shuts down the server it came from. Clone it to hand to other threads.
+#[derive(Clone)]
+pub struct ServerHandle {
+    shared: Arc<Shared>,
+}
+
+impl ServerHandle {
+    /// Make the server's `run` stop accepting connections and return once the
+    /// requests it's handling are answered. Calling it again does nothing.
+    pub fn shutdown(&self) {
+        if self.shared.shutting_down.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        //wake the accept loop, which is blocked waiting for a connection
+        let _ = TcpStream::connect(self.shared.address);
+    }
+}
+
+/// How long and for how many requests a connection may be used, from `Config`
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    keep_alive: Duration,
+    request_timeout: Duration,
+    max_requests: usize,
+}
+
+/// What serve_connection needs of a connection besides reading and writing
+trait Connection {
+    /// Make reads that wait longer than timeout fail with `WouldBlock` or `TimedOut`
+    fn set_read_timeout(&self, timeout: Duration) -> io::Result<()>;
+    /// Mark the connection idle, between requests, or busy with one. Shutting
+    /// down closes idle connections, so this gives false if the connection has
+    /// been closed or, when marking it idle, the server is shutting down.
+    fn set_idle(&self, idle: bool) -> bool;
+}
+
+/// A connection a server is keeping track of
+struct Tracked<'a> {
+    stream: &'a TcpStream,
+    shared: &'a Shared,
+    id: usize,
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let buf_reader = BufReader::new(&stream);
-    let mut http_request = buf_reader.lines();
-    let http_request_line = http_request.next().unwrap().unwrap();
+impl Connection for Tracked<'_> {
+    fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        self.stream.set_read_timeout(Some(timeout))
+    }
 
-    //handle routes
-    let (status_line, file_name) = match &http_request_line[..] {
-        "GET / HTTP/1.1" => ("HTTP/1.1 200 OK\r\n", "welcome.html"),
-        "GET /sleep HTTP/1.1" => {
-            thread::sleep(Duration::from_secs(5));
-            ("HTTP/1.1 200 OK\r\n", "welcome.html")
+    fn set_idle(&self, idle: bool) -> bool {
+        let mut connections = self.shared.connections.lock().unwrap();
+        //shutting down forgets the idle connections it closes
+        let Some(open) = connections.get_mut(&self.id) else {
+            return false;
+        };
+        if idle && self.shared.shutting_down.load(Ordering::SeqCst) {
+            return false;
         }
-        _ => ("HTTP/1.1 400 NOT FOUND\r\n", "error.html"),
+        open.idle = idle;
+        true
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    router: &Router,
+    limits: Limits,
+    shared: &Shared,
+    id: usize,
+) -> io::Result<()> {
+    //send each response as soon as it's written, rather than hold back its
+    //body until the client acknowledges the head
+    stream.set_nodelay(true)?;
+    //and don't let a client that stops reading tie up the worker for good
+    stream.set_write_timeout(Some(limits.request_timeout))?;
+    let connection = Tracked {
+        stream: &stream,
+        shared,
+        id,
     };
+    serve_connection(
+        BufReader::new(&stream),
+        BufWriter::new(&stream),
+        router,
+        limits,
+        &shared.shutting_down,
+        &connection,
+    )
+}
+
+/// Answer the requests on a connection in order until the client closes it,
+/// asks to close it, goes idle, has made max_requests of them or the server
+/// starts shutting down. Requests the client pipelines, sending several
+/// before reading any response, wait in reader's buffer.
+fn serve_connection(
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+    router: &Router,
+    limits: Limits,
+    shutting_down: &AtomicBool,
+    connection: &impl Connection,
+) -> io::Result<()> {
+    for served in 1..=limits.max_requests.max(1) {
+        //a connection that hasn't made a request yet gets to make one even while shutting down
+        if served > 1 && !connection.set_idle(true) {
+            return Ok(());
+        }
+        //only waiting for the next request to start is held to the keep-alive timeout
+        connection.set_read_timeout(limits.keep_alive)?;
+        match reader.fill_buf() {
+            //the client closed the connection between requests
+            Ok([]) => return Ok(()),
+            Ok(_) => {}
+            //so did an idle client, by not sending anything before the timeout
+            Err(err) if is_timeout(&err) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        //shutting down closed the connection as the request started to arrive, so it may be cut off
+        if served > 1 && !connection.set_idle(false) {
+            return Ok(());
+        }
+        connection.set_read_timeout(limits.request_timeout)?;
+
+        let (mut response, keep_open) = match Request::read_from(&mut reader) {
+            Ok(Some(request)) => {
+                //print HTTP Request to console
+                println!(
+                    "Connection Established. HTTP Req => {} {} {}\n{:#?}",
+                    request.method, request.path, request.version, request.headers
+                );
+                let response = match request.method.as_str() {
+                    "HEAD" => router.handle(&request).without_body(),
+                    _ => router.handle(&request),
+                };
+                let keep_open =
+                    wants_keep_alive(&request) && !has_close(response.header_value("Connection"));
+                if keep_open && request.version == Version::Http10 {
+                    (response.header("Connection", "keep-alive"), true)
+                } else {
+                    (response, keep_open)
+                }
+            }
+            Ok(None) => return Ok(()),
+            //including a request the client stopped sending partway through, which gets a 408
+            Err(err) => {
+                println!("Connection Established. Bad HTTP Req => {err}");
+                let response = Response::new(err.status())
+                    .header("Content-Type", "text/plain; charset=utf-8")
+                    .body(format!("{err}\n"));
+                (response, false)
+            }
+        };
+
+        let keep_open =
+            keep_open && served < limits.max_requests && !shutting_down.load(Ordering::SeqCst);
+        if !keep_open && !has_close(response.header_value("Connection")) {
+            response = response.header("Connection", "close");
+        }
+        response.write_to(&mut writer)?;
+        if !keep_open {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the client wants the connection kept open after request, which
+/// HTTP/1.1 clients do unless they say otherwise and HTTP/1.0 clients don't
+fn wants_keep_alive(request: &Request) -> bool {
+    let connection = request.header("Connection");
+    match request.version {
+        Version::Http11 => !has_close(connection),
+        Version::Http10 => connection.is_some_and(|options| {
+            options
+                .split(',')
+                .any(|option| option.trim().eq_ignore_ascii_case("keep-alive"))
+        }),
+    }
+}
 
-    //print HTTP Request to console
-    let http_request: Vec<_> = http_request
-        .map(|result| result.unwrap())
-        .take_while(|line| !line.is_empty())
-        .collect();
-    println!("Connection Established. HTTP Req => {http_request_line}\n{http_request:#?}");
+/// Whether err is a read giving up after its timeout, which depending on the
+/// platform is `WouldBlock` or `TimedOut`
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
 
-    let contents = fs::read_to_string(file_name).unwrap();
-    let content_length = contents.len();
-    let response = format!("{status_line}Content-Length: {content_length}\r\n\r\n{contents}");
+/// Whether a `Connection` header value asks for the connection to be closed
+fn has_close(connection: Option<&str>) -> bool {
+    connection.is_some_and(|options| {
+        options
+            .split(',')
+            .any(|option| option.trim().eq_ignore_ascii_case("close"))
+    })
+}
 
-    stream.write_all(response.as_bytes()).unwrap();
+/// A response with the contents of an HTML file in the working directory
+fn html_file(status: u16, file_name: &str) -> Response {
+    match fs::read(file_name) {
+        Ok(contents) => Response::new(status)
+            .header("Content-Type", "text/html; charset=utf-8")
+            .body(contents),
+        Err(err) => {
+            println!("Failed to read {file_name}: {err}");
+            Response::new(500)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router() -> Router {
+        Router::new()
+            .get("/", |_, _| Response::new(200).body("home"))
+            .get("/bye", |_, _| {
+                Response::new(200).header("Connection", "close")
+            })
+    }
+
+    /// A connection no server is keeping track of
+    impl Connection for () {
+        fn set_read_timeout(&self, _: Duration) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn set_idle(&self, _: bool) -> bool {
+            true
+        }
+    }
+
+    /// A client that stops sending
+    struct Stalled;
+
+    impl Read for Stalled {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    //the responses written for what reader gives, sent all at once as a pipelining client would
+    fn converse_with(reader: impl BufRead, max_requests: usize) -> String {
+        let mut written = Vec::new();
+        let limits = Limits {
+            keep_alive: KEEP_ALIVE,
+            request_timeout: REQUEST_TIMEOUT,
+            max_requests,
+        };
+        let shutting_down = AtomicBool::new(false);
+        serve_connection(reader, &mut written, &router(), limits, &shutting_down, &()).unwrap();
+        String::from_utf8(written).unwrap()
+    }
+
+    fn converse(raw: &str, max_requests: usize) -> String {
+        converse_with(raw.as_bytes(), max_requests)
+    }
+
+    fn statuses(written: &str) -> Vec<&str> {
+        written
+            .split("HTTP/1.1 ")
+            .skip(1)
+            .map(|response| &response[..3])
+            .collect()
+    }
+
+    #[test]
+    fn pipelined_requests_are_answered_in_order() {
+        let written = converse(
+            "GET / HTTP/1.1\r\nHost: x\r\n\r\nGET /nowhere HTTP/1.1\r\nHost: x\r\n\r\nGET / HTTP/1.1\r\nHost: x\r\n\r\n",
+            100,
+        );
+        assert_eq!(vec!["200", "404", "200"], statuses(&written));
+        //the connection stays open until the client closes it
+        assert!(!written.contains("Connection: close"));
+        assert!(written.ends_with("Content-Length: 4\r\n\r\nhome"));
+    }
+
+    #[test]
+    fn closing_the_connection() {
+        let twice = "GET / HTTP/1.1\r\nHost: x\r\n\r\n".repeat(2);
+
+        //by the client
+        let close = "GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+        assert_eq!(
+            vec!["200"],
+            statuses(&converse(&format!("{close}{twice}"), 100))
+        );
+        //by a handler
+        let bye = "GET /bye HTTP/1.1\r\nHost: x\r\n\r\n";
+        assert_eq!(
+            vec!["200"],
+            statuses(&converse(&format!("{bye}{twice}"), 100))
+        );
+        //after the last request allowed
+        let written = converse(&twice, 1);
+        assert_eq!(
+            (vec!["200"], true),
+            (statuses(&written), written.contains("Connection: close"))
+        );
+        //after a bad request
+        let bad = "GET / HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            vec!["400"],
+            statuses(&converse(&format!("{bad}{twice}"), 100))
+        );
+    }
+
+    #[test]
+    fn timeouts() {
+        //an idle client is let go without a response
+        let idle = "GET / HTTP/1.1\r\nHost: x\r\n\r\n"
+            .as_bytes()
+            .chain(Stalled);
+        let written = converse_with(BufReader::new(idle), 100);
+        assert_eq!(
+            (vec!["200"], false),
+            (statuses(&written), written.contains("Connection: close"))
+        );
+        //one that stops partway through a request is told why
+        let partial = "GET / HTTP/1.1\r\nHo".as_bytes().chain(Stalled);
+        let written = converse_with(BufReader::new(partial), 100);
+        assert_eq!(
+            (vec!["408"], true),
+            (statuses(&written), written.contains("Connection: close"))
+        );
+    }
+
+    #[test]
+    fn http_10_closes_unless_asked_not_to() {
+        let once = "GET / HTTP/1.0\r\n\r\n";
+        assert_eq!(vec!["200"], statuses(&converse(&once.repeat(2), 100)));
+
+        let keep_alive = "GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n";
+        let written = converse(&format!("{keep_alive}{once}"), 100);
+        assert_eq!(vec!["200", "200"], statuses(&written));
+        assert_eq!(1, written.matches("Connection: keep-alive").count());
+    }
 }
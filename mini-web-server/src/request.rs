@@ -0,0 +1,544 @@
+//! Parsing HTTP/1.x requests
+
+use std::{
+    error::Error,
+    fmt,
+    io::{self, prelude::*},
+};
+
+//limits on what a client may send, so one connection can't exhaust a worker's memory
+const MAX_REQUEST_LINE: usize = 8 * 1024;
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+const MAX_HEADERS: usize = 100;
+const MAX_BODY: usize = 8 * 1024 * 1024;
+
+/// The HTTP version a request was made with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Version::Http10 => write!(f, "HTTP/1.0"),
+            Version::Http11 => write!(f, "HTTP/1.1"),
+        }
+    }
+}
+
+/// A parsed HTTP request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    /// The method, like `GET` or `POST`, as sent
+    pub method: String,
+    /// The percent-decoded path of the request target, always starting with `/`
+    /// (or just `*` for `OPTIONS *`)
+    pub path: String,
+    /// The path as sent, still percent-encoded, so an encoded `/` can be told
+    /// apart from one between segments
+    pub raw_path: String,
+    /// The query string after `?`, still percent-encoded
+    pub query: Option<String>,
+    pub version: Version,
+    /// Header names and values in the order they were sent
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Read the next request from reader. Returns `Ok(None)` if the client
+    /// closed the connection before sending anything.
+    pub fn read_from(reader: &mut impl BufRead) -> Result<Option<Request>, ParseError> {
+        let Some(request_line) = read_line(reader, MAX_REQUEST_LINE, ParseError::UriTooLong)?
+        else {
+            return Ok(None);
+        };
+        let (method, target, version) = parse_request_line(&request_line)?;
+        let (raw_path, query) = parse_target(&method, target)?;
+        let path = percent_decode(&raw_path)?;
+        let headers = read_headers(reader)?;
+
+        let mut request = Request {
+            method,
+            path,
+            raw_path,
+            query,
+            version,
+            headers,
+            body: Vec::new(),
+        };
+        if request.version == Version::Http11 && request.header("Host").is_none() {
+            return Err(ParseError::BadRequest("missing Host header"));
+        }
+        request.body = read_body(reader, &request)?;
+
+        Ok(Some(request))
+    }
+
+    /// The value of the first header called name, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The values of every header called name, ignoring case
+    pub fn headers_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.headers
+            .iter()
+            .filter(move |(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Why a request couldn't be parsed
+#[derive(Debug)]
+pub enum ParseError {
+    /// The request is malformed; the reason is for logging
+    BadRequest(&'static str),
+    /// The request line is too long, almost always because of its target
+    UriTooLong,
+    /// Too many headers, or too many bytes of them
+    HeadersTooLarge,
+    /// The body is longer than the server accepts
+    PayloadTooLarge,
+    /// An HTTP version other than 1.0 and 1.1
+    VersionNotSupported,
+    /// Reading from the connection failed, or it closed partway through a request
+    Io(io::Error),
+}
+
+impl ParseError {
+    /// The status code of the response to send for this error
+    pub fn status(&self) -> u16 {
+        match self {
+            //the client stopped sending partway through the request
+            ParseError::Io(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                408
+            }
+            ParseError::BadRequest(_) | ParseError::Io(_) => 400,
+            ParseError::UriTooLong => 414,
+            ParseError::HeadersTooLarge => 431,
+            ParseError::PayloadTooLarge => 413,
+            ParseError::VersionNotSupported => 505,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::BadRequest(reason) => write!(f, "bad request: {reason}"),
+            ParseError::UriTooLong => {
+                write!(f, "request line longer than {MAX_REQUEST_LINE} bytes")
+            }
+            ParseError::HeadersTooLarge => write!(
+                f,
+                "more than {MAX_HEADERS} headers or {MAX_HEADER_BYTES} bytes of them"
+            ),
+            ParseError::PayloadTooLarge => write!(f, "body longer than {MAX_BODY} bytes"),
+            ParseError::VersionNotSupported => write!(f, "unsupported HTTP version"),
+            ParseError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(err: io::Error) -> ParseError {
+        ParseError::Io(err)
+    }
+}
+
+/// Read one line of at most limit bytes, without its `\r\n` or `\n`. Returns
+/// `None` at the end of the stream and too_long if the line doesn't fit.
+fn read_line(
+    reader: &mut impl BufRead,
+    limit: usize,
+    too_long: ParseError,
+) -> Result<Option<String>, ParseError> {
+    let mut line = Vec::new();
+    //one byte over the limit is enough to tell the line is too long
+    reader.take(limit as u64 + 1).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") {
+        return match line.len() > limit {
+            true => Err(too_long),
+            false => Err(ParseError::Io(io::ErrorKind::UnexpectedEof.into())),
+        };
+    }
+
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| ParseError::BadRequest("line is not valid UTF-8"))
+}
+
+/// Split `METHOD target HTTP/x.y` into its parts
+fn parse_request_line(line: &str) -> Result<(String, &str, Version), ParseError> {
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::BadRequest(
+            "request line is not method, target and version",
+        ));
+    };
+
+    if !is_token(method) {
+        return Err(ParseError::BadRequest("invalid method"));
+    }
+    if target.is_empty() {
+        return Err(ParseError::BadRequest("empty request target"));
+    }
+    let version = match version {
+        "HTTP/1.1" => Version::Http11,
+        "HTTP/1.0" => Version::Http10,
+        _ if is_http_version(version) => return Err(ParseError::VersionNotSupported),
+        _ => return Err(ParseError::BadRequest("invalid HTTP version")),
+    };
+
+    Ok((method.to_string(), target, version))
+}
+
+/// Whether version looks like `HTTP/` followed by a digit, a dot and a digit
+fn is_http_version(version: &str) -> bool {
+    match version.strip_prefix("HTTP/").map(str::as_bytes) {
+        Some([major, b'.', minor]) => major.is_ascii_digit() && minor.is_ascii_digit(),
+        _ => false,
+    }
+}
+
+/// The path and query of a request target, both still percent-encoded. The
+/// target is usually just a path but may be a whole URL when the client talks
+/// to us as a proxy.
+fn parse_target(method: &str, target: &str) -> Result<(String, Option<String>), ParseError> {
+    if target == "*" {
+        return match method {
+            "OPTIONS" => Ok((String::from("*"), None)),
+            _ => Err(ParseError::BadRequest("* target outside OPTIONS")),
+        };
+    }
+
+    //a :// in the query, like /go?to=http://x/y, doesn't make the target a URL
+    let origin = match target.starts_with('/') {
+        true => target.to_string(),
+        false => {
+            let after_scheme = strip_prefix_ignore_case(target, "http://")
+                .or_else(|| strip_prefix_ignore_case(target, "https://"))
+                .ok_or(ParseError::BadRequest("request target is not a path"))?;
+            //the authority runs up to the path, or straight to a query
+            match after_scheme.find(['/', '?', '#']) {
+                Some(end) if after_scheme[end..].starts_with('/') => {
+                    after_scheme[end..].to_string()
+                }
+                Some(end) => format!("/{}", &after_scheme[end..]),
+                None => String::from("/"),
+            }
+        }
+    };
+
+    //a fragment is never sent, but if it is it isn't part of the resource
+    let origin = origin.split('#').next().unwrap_or_default();
+    let (path, query) = match origin.split_once('?') {
+        Some((path, query)) => (path, Some(query.to_string())),
+        None => (origin, None),
+    };
+
+    Ok((path.to_string(), query))
+}
+
+/// s without prefix, which is matched ignoring ASCII case
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &s[prefix.len()..])
+}
+
+/// Decode `%XX` escapes in path
+pub(crate) fn percent_decode(path: &str) -> Result<String, ParseError> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or(ParseError::BadRequest("invalid percent-encoding"))?;
+            decoded.push(hex);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| ParseError::BadRequest("path is not valid UTF-8"))
+}
+
+fn read_headers(reader: &mut impl BufRead) -> Result<Vec<(String, String)>, ParseError> {
+    let mut headers = Vec::new();
+    let mut remaining = MAX_HEADER_BYTES;
+
+    loop {
+        let line = read_line(reader, remaining, ParseError::HeadersTooLarge)?
+            .ok_or(ParseError::Io(io::ErrorKind::UnexpectedEof.into()))?;
+        remaining = remaining.saturating_sub(line.len() + 2);
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        if headers.len() == MAX_HEADERS || remaining == 0 {
+            return Err(ParseError::HeadersTooLarge);
+        }
+        //folding a value onto the next line is obsolete and a known smuggling vector
+        if line.starts_with([' ', '\t']) {
+            return Err(ParseError::BadRequest("folded header line"));
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(ParseError::BadRequest("header line without a colon"))?;
+        if !is_token(name) {
+            return Err(ParseError::BadRequest("invalid header name"));
+        }
+        headers.push((
+            name.to_string(),
+            value.trim_matches([' ', '\t']).to_string(),
+        ));
+    }
+}
+
+/// The body as framed by `Transfer-Encoding: chunked` or `Content-Length`;
+/// without either a request has no body
+fn read_body(reader: &mut impl BufRead, request: &Request) -> Result<Vec<u8>, ParseError> {
+    let transfer_encoding = request.header("Transfer-Encoding");
+    let mut lengths = request.headers_named("Content-Length");
+    let content_length = lengths.next();
+
+    match (transfer_encoding, content_length) {
+        //both at once is how requests get smuggled past proxies
+        (Some(_), Some(_)) => Err(ParseError::BadRequest(
+            "both Transfer-Encoding and Content-Length",
+        )),
+        (Some(encoding), None) => {
+            let chunked = encoding
+                .rsplit(',')
+                .next()
+                .is_some_and(|last| last.trim().eq_ignore_ascii_case("chunked"));
+            match chunked {
+                true => read_chunked(reader),
+                false => Err(ParseError::BadRequest("unsupported Transfer-Encoding")),
+            }
+        }
+        (None, Some(length)) => {
+            if lengths.any(|other| other != length) {
+                return Err(ParseError::BadRequest("conflicting Content-Length headers"));
+            }
+            if length.is_empty() || !length.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(ParseError::BadRequest("invalid Content-Length"));
+            }
+            let length: usize = length.parse().map_err(|_| ParseError::PayloadTooLarge)?;
+            if length > MAX_BODY {
+                return Err(ParseError::PayloadTooLarge);
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            Ok(body)
+        }
+        (None, None) => Ok(Vec::new()),
+    }
+}
+
+/// Decode a chunked body: hex sizes each followed by that many bytes, ending
+/// with a zero size and optional trailers, which are dropped
+fn read_chunked(reader: &mut impl BufRead) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+
+    loop {
+        let line = read_line(
+            reader,
+            MAX_REQUEST_LINE,
+            ParseError::BadRequest("chunk size line too long"),
+        )?
+        .ok_or(ParseError::Io(io::ErrorKind::UnexpectedEof.into()))?;
+        //chunk extensions after ; carry nothing we use
+        let size = line.split(';').next().unwrap_or_default().trim();
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseError::BadRequest("invalid chunk size"));
+        }
+        let size = usize::from_str_radix(size, 16).map_err(|_| ParseError::PayloadTooLarge)?;
+        if size == 0 {
+            break;
+        }
+        //sizes of up to 16 hex digits would overflow a sum
+        if size > MAX_BODY - body.len() {
+            return Err(ParseError::PayloadTooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        let mut crlf = [0; 2];
+        reader.read_exact(&mut crlf)?;
+        if &crlf != b"\r\n" {
+            return Err(ParseError::BadRequest("chunk not followed by CRLF"));
+        }
+    }
+
+    read_headers(reader)?;
+    Ok(body)
+}
+
+/// Whether s is a non-empty run of the characters allowed in methods and header names
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<Option<Request>, ParseError> {
+        Request::read_from(&mut raw.as_bytes())
+    }
+
+    fn status(raw: &str) -> u16 {
+        parse(raw).unwrap_err().status()
+    }
+
+    #[test]
+    fn request_with_query_and_headers() {
+        let request = parse("GET /hello%20world?name=frog&x=1 HTTP/1.1\r\nHost: localhost\r\nAccept:  text/html \r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!("GET", request.method);
+        assert_eq!("/hello world", request.path);
+        assert_eq!("/hello%20world", request.raw_path);
+        assert_eq!(Some("name=frog&x=1"), request.query.as_deref());
+        assert_eq!(Version::Http11, request.version);
+        assert_eq!(Some("text/html"), request.header("accept"));
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn targets() {
+        let target = |target: &str| {
+            let raw = format!("GET {target} HTTP/1.1\r\nHost: x\r\n\r\n");
+            let request = parse(&raw).unwrap().unwrap();
+            (request.path, request.query)
+        };
+        let query = |query: &str| Some(query.to_string());
+        assert_eq!(
+            (String::from("/go"), query("to=http://evil.example/admin")),
+            target("/go?to=http://evil.example/admin")
+        );
+        assert_eq!(
+            (String::from("/a b"), query("x=1")),
+            target("HTTP://example.com/a%20b?x=1")
+        );
+        assert_eq!(
+            (String::from("/"), query("x=1")),
+            target("https://example.com?x=1")
+        );
+        assert_eq!(
+            400,
+            status("GET ftp://example.com/ HTTP/1.1\r\nHost: x\r\n\r\n")
+        );
+        assert_eq!(400, status("GET example.com/ HTTP/1.1\r\nHost: x\r\n\r\n"));
+    }
+
+    #[test]
+    fn http_10_needs_no_host() {
+        let request = parse("GET http://example.com HTTP/1.0\n\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            ("/", Version::Http10),
+            (request.path.as_str(), request.version)
+        );
+    }
+
+    #[test]
+    fn bodies() {
+        let raw = "POST /form HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhelloGET";
+        assert_eq!(b"hello".to_vec(), parse(raw).unwrap().unwrap().body);
+
+        let raw = "POST /up HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+                   5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nTrailer: yes\r\n\r\n";
+        assert_eq!(b"hello, world".to_vec(), parse(raw).unwrap().unwrap().body);
+    }
+
+    #[test]
+    fn pipelined_requests_are_read_one_at_a_time() {
+        let mut raw =
+            "GET /a HTTP/1.1\r\nHost: x\r\n\r\nGET /b HTTP/1.1\r\nHost: x\r\n\r\n".as_bytes();
+        assert_eq!("/a", Request::read_from(&mut raw).unwrap().unwrap().path);
+        assert_eq!("/b", Request::read_from(&mut raw).unwrap().unwrap().path);
+        assert!(Request::read_from(&mut raw).unwrap().is_none());
+    }
+
+    #[test]
+    fn errors_map_to_statuses() {
+        assert_eq!(400, status("GET /\r\n\r\n"));
+        assert_eq!(400, status("GET / HTTP/1.1\r\n\r\n"));
+        assert_eq!(400, status("GET /%zz HTTP/1.1\r\nHost: x\r\n\r\n"));
+        assert_eq!(400, status("G(T / HTTP/1.1\r\nHost: x\r\n\r\n"));
+        assert_eq!(400, status("GET / HTTP/1.1\r\nHost: x\r\n folded\r\n\r\n"));
+        assert_eq!(
+            400,
+            status("POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n")
+        );
+        assert_eq!(
+            400,
+            status("POST / HTTP/1.1\r\nHost: x\r\nContent-Length: -1\r\n\r\n")
+        );
+        assert_eq!(400, status("GET / HTTP/1.1\r\nHost: x\r\n"));
+        assert_eq!(
+            414,
+            status(&format!(
+                "GET /{} HTTP/1.1\r\n\r\n",
+                "a".repeat(MAX_REQUEST_LINE)
+            ))
+        );
+        let many = "X-Frog: 1\r\n".repeat(MAX_HEADERS + 1);
+        assert_eq!(
+            431,
+            status(&format!("GET / HTTP/1.1\r\nHost: x\r\n{many}\r\n"))
+        );
+        let huge = format!("X-Frog: {}\r\n", "a".repeat(MAX_HEADER_BYTES));
+        assert_eq!(431, status(&format!("GET / HTTP/1.1\r\n{huge}\r\n")));
+        assert_eq!(
+            413,
+            status("POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 99999999999\r\n\r\n")
+        );
+        assert_eq!(
+            413,
+            status("POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\nffffffffffffffff\r\n")
+        );
+        assert_eq!(505, status("GET / HTTP/2.0\r\n\r\n"));
+    }
+}
@@ -0,0 +1,221 @@
+//! Building and writing HTTP responses
+
+use std::{
+    fmt,
+    io::{self, prelude::*},
+};
+
+/// An HTTP/1.1 response
+pub struct Response {
+    pub status: u16,
+    /// Header names and values in the order they will be sent. `Content-Length`
+    /// is added when the response is written, unless it's already here or the
+    /// status can't have a body.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// A body read from a reader as it's written, instead of `body`, and its length
+    stream: Option<(Box<dyn Read + Send>, u64)>,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            stream: None,
+        }
+    }
+
+    /// Add a header
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Response {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// Set the body
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Response {
+        self.body = body.into();
+        self.stream = None;
+        self
+    }
+
+    /// Set the body to the next len bytes of reader, copied to the client as
+    /// the response is written rather than held in memory
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_web_server::Response;
+    /// let mut written = Vec::new();
+    /// Response::new(200)
+    ///     .stream(&b"hello, world"[..], 5)
+    ///     .write_to(&mut written)
+    ///     .unwrap();
+    /// assert!(written.ends_with(b"Content-Length: 5\r\n\r\nhello"));
+    /// ```
+    pub fn stream(mut self, reader: impl Read + Send + 'static, len: u64) -> Response {
+        self.body.clear();
+        self.stream = Some((Box::new(reader), len));
+        self
+    }
+
+    /// The value of the first header called name, ignoring case
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// This response as the answer to a `HEAD` request: the same headers,
+    /// including the length of the body, but no body
+    pub fn without_body(mut self) -> Response {
+        if self.header_value("Content-Length").is_none() && has_body(self.status) {
+            let len = self.len().to_string();
+            self = self.header("Content-Length", len);
+        }
+        self.body.clear();
+        self.stream = None;
+        self
+    }
+
+    /// Write the status line, headers and body to writer. A streamed body
+    /// that ends early is an `UnexpectedEof` error, as the client can't be
+    /// sent the rest of what the `Content-Length` promised.
+    pub fn write_to(mut self, writer: &mut impl Write) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if self.header_value("Content-Length").is_none() && has_body(self.status) {
+            head.push_str(&format!("Content-Length: {}\r\n", self.len()));
+        }
+        head.push_str("\r\n");
+
+        writer.write_all(head.as_bytes())?;
+        writer.write_all(&self.body)?;
+        if let Some((reader, len)) = self.stream.take() {
+            if io::copy(&mut reader.take(len), writer)? < len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        writer.flush()
+    }
+
+    /// The length of the body
+    fn len(&self) -> u64 {
+        match &self.stream {
+            Some((_, len)) => *len,
+            None => self.body.len() as u64,
+        }
+    }
+}
+
+impl fmt::Debug for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut response = f.debug_struct("Response");
+        response
+            .field("status", &self.status)
+            .field("headers", &self.headers);
+        match &self.stream {
+            Some((_, len)) => response.field("stream", len),
+            None => response.field("body", &self.body),
+        };
+        response.finish()
+    }
+}
+
+/// Whether a response with status may have a body, and so a `Content-Length`
+fn has_body(status: u16) -> bool {
+    !(100..200).contains(&status) && status != 204 && status != 304
+}
+
+/// The standard reason phrase for a status code
+pub fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        416 => "Range Not Satisfiable",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_head_and_body() {
+        let mut written = Vec::new();
+        Response::new(404)
+            .header("Content-Type", "text/plain")
+            .body("gone")
+            .write_to(&mut written)
+            .unwrap();
+        assert_eq!(
+            "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: 4\r\n\r\ngone",
+            String::from_utf8(written).unwrap()
+        );
+    }
+
+    #[test]
+    fn bodiless_responses() {
+        let mut written = Vec::new();
+        Response::new(200)
+            .body("hello")
+            .without_body()
+            .write_to(&mut written)
+            .unwrap();
+        assert_eq!(
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n",
+            String::from_utf8(written).unwrap()
+        );
+
+        let mut written = Vec::new();
+        Response::new(304)
+            .header("ETag", "\"1\"")
+            .write_to(&mut written)
+            .unwrap();
+        assert_eq!(
+            "HTTP/1.1 304 Not Modified\r\nETag: \"1\"\r\n\r\n",
+            String::from_utf8(written).unwrap()
+        );
+    }
+
+    #[test]
+    fn streamed_bodies() {
+        let mut written = Vec::new();
+        Response::new(200)
+            .stream(&b"hello"[..], 5)
+            .without_body()
+            .write_to(&mut written)
+            .unwrap();
+        assert_eq!(
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n",
+            String::from_utf8(written).unwrap()
+        );
+
+        //a file that shrank since its length was taken
+        let err = Response::new(200)
+            .stream(&b"hell"[..], 5)
+            .write_to(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+}
@@ -0,0 +1,368 @@
+//! Dispatching requests to handlers by method and path
+
+use crate::{
+    request::{percent_decode, Request},
+    response::Response,
+};
+
+/// A function that answers a request whose path matched its route
+pub type Handler = Box<dyn Fn(&Request, &Params) -> Response + Send + Sync + 'static>;
+
+/// Routes requests to the handler registered for their method and path
+///
+/// Patterns are paths whose segments may be `:name`, which matches any one
+/// non-empty segment, or, as the last segment, `*name` or `*`, which matches
+/// the rest of the path. When several routes match, the first registered wins.
+///
+/// # Examples
+///
+/// ```
+/// use mini_web_server::{Request, Response, Router};
+/// let router = Router::new()
+///     .get("/users/:id", |_, params| {
+///         Response::new(200).body(format!("user {}", &params["id"]))
+///     })
+///     .get("/files/*path", |_, params| {
+///         Response::new(200).body(params["path"].to_string())
+///     });
+///
+/// let mut raw = "GET /users/42 HTTP/1.1\r\nHost: x\r\n\r\n".as_bytes();
+/// let request = Request::read_from(&mut raw).unwrap().unwrap();
+/// assert_eq!(b"user 42".to_vec(), router.handle(&request).body);
+/// ```
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    not_found: Option<Handler>,
+}
+
+struct Route {
+    method: String,
+    pattern: Vec<Segment>,
+    handler: Handler,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(Option<String>),
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// Register handler for requests with method whose path matches pattern
+    ///
+    /// # Panics
+    ///
+    /// Panics if pattern doesn't start with `/`, has a `:` parameter with no
+    /// name, or has a wildcard that isn't the last segment.
+    pub fn route<F>(mut self, method: &str, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            method: method.to_string(),
+            pattern: parse_pattern(pattern),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Register handler for `GET` requests
+    pub fn get<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("GET", pattern, handler)
+    }
+
+    /// Register handler for `POST` requests
+    pub fn post<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("POST", pattern, handler)
+    }
+
+    /// Register handler for `PUT` requests
+    pub fn put<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("PUT", pattern, handler)
+    }
+
+    /// Register handler for `DELETE` requests
+    pub fn delete<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("DELETE", pattern, handler)
+    }
+
+    /// Answer requests no route matches with handler instead of an empty `404`
+    pub fn not_found<F>(mut self, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.not_found = Some(Box::new(handler));
+        self
+    }
+
+    /// Answer request with the first matching route's handler, or a `HEAD`
+    /// request with the first matching `GET` route's if no `HEAD` route
+    /// matches; the server drops the body of responses to `HEAD`. A path no
+    /// route matches gets a `404 Not Found` or the `not_found` handler's
+    /// response, and a path that only matches routes for other methods a `405
+    /// Method Not Allowed` listing them in `Allow`.
+    pub fn handle(&self, request: &Request) -> Response {
+        let mut allowed: Vec<&str> = Vec::new();
+        let mut get = None;
+
+        for route in &self.routes {
+            let Some(params) = match_path(&route.pattern, &request.raw_path) else {
+                continue;
+            };
+            if route.method == request.method {
+                return (route.handler)(request, &params);
+            }
+            if !allowed.contains(&route.method.as_str()) {
+                allowed.push(&route.method);
+            }
+            if route.method == "GET" {
+                get = get.or(Some((route, params)));
+                if !allowed.contains(&"HEAD") {
+                    allowed.push("HEAD");
+                }
+            }
+        }
+
+        if let (Some((route, params)), "HEAD") = (get, request.method.as_str()) {
+            return (route.handler)(request, &params);
+        }
+
+        match (allowed.is_empty(), &self.not_found) {
+            (true, Some(not_found)) => not_found(request, &Params::default()),
+            (true, None) => Response::new(404),
+            (false, _) => Response::new(405).header("Allow", allowed.join(", ")),
+        }
+    }
+}
+
+/// Values of the `:name` and `*name` segments of a matched route
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params {
+    params: Vec<(String, String)>,
+}
+
+impl Params {
+    /// The value matched by the segment called name
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Names and values in the order they appear in the pattern
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+impl std::ops::Index<&str> for Params {
+    type Output = str;
+
+    /// # Panics
+    ///
+    /// Panics if the route has no segment called name.
+    fn index(&self, name: &str) -> &str {
+        self.get(name)
+            .unwrap_or_else(|| panic!("no route parameter called {name}"))
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    let Some(path) = pattern.strip_prefix('/') else {
+        panic!("route pattern {pattern:?} doesn't start with /");
+    };
+
+    let segments: Vec<Segment> = path
+        .split('/')
+        .map(|segment| {
+            if let Some(name) = segment.strip_prefix(':') {
+                assert!(!name.is_empty(), "unnamed parameter in route {pattern:?}");
+                Segment::Param(name.to_string())
+            } else if let Some(name) = segment.strip_prefix('*') {
+                Segment::Wildcard((!name.is_empty()).then(|| name.to_string()))
+            } else {
+                Segment::Literal(segment.to_string())
+            }
+        })
+        .collect();
+
+    let wildcards = segments
+        .iter()
+        .position(|segment| matches!(segment, Segment::Wildcard(_)));
+    if let Some(position) = wildcards {
+        assert!(
+            position == segments.len() - 1,
+            "wildcard before the end of route {pattern:?}"
+        );
+    }
+
+    segments
+}
+
+/// The parameters of pattern if path matches it. path is still percent-encoded,
+/// so it's split into segments before each is decoded, and an encoded `/` is
+/// part of a segment rather than a boundary between two.
+fn match_path(pattern: &[Segment], path: &str) -> Option<Params> {
+    let parts: Vec<&str> = path.strip_prefix('/')?.split('/').collect();
+    let decoded = |part: &str| percent_decode(part).ok();
+    let mut params = Params::default();
+
+    for (i, segment) in pattern.iter().enumerate() {
+        match segment {
+            Segment::Wildcard(name) => {
+                if let Some(name) = name {
+                    let rest = parts.get(i..).unwrap_or_default().join("/");
+                    params.params.push((name.clone(), decoded(&rest)?));
+                }
+                return Some(params);
+            }
+            Segment::Literal(literal) => {
+                if parts.get(i).and_then(|part| decoded(part)).as_ref() != Some(literal) {
+                    return None;
+                }
+            }
+            Segment::Param(name) => match parts.get(i) {
+                Some(part) if !part.is_empty() => {
+                    params.params.push((name.clone(), decoded(part)?));
+                }
+                _ => return None,
+            },
+        }
+    }
+
+    (parts.len() == pattern.len()).then_some(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str) -> Request {
+        let raw = format!("{method} {path} HTTP/1.1\r\nHost: x\r\n\r\n");
+        Request::read_from(&mut raw.as_bytes()).unwrap().unwrap()
+    }
+
+    fn params(pattern: &str, path: &str) -> Option<Vec<(String, String)>> {
+        match_path(&parse_pattern(pattern), path).map(|params| params.params)
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn matching_paths() {
+        assert_eq!(pairs(&[]), params("/", "/"));
+        assert_eq!(None, params("/", "/users"));
+        assert_eq!(pairs(&[]), params("/users", "/users"));
+        assert_eq!(None, params("/users", "/users/"));
+        assert_eq!(None, params("/users", "/"));
+        assert_eq!(
+            pairs(&[("id", "42"), ("post", "7")]),
+            params("/users/:id/posts/:post", "/users/42/posts/7")
+        );
+        assert_eq!(None, params("/users/:id", "/users/"));
+        assert_eq!(None, params("/users/:id", "/users/42/posts"));
+        assert_eq!(
+            pairs(&[("path", "css/site.css")]),
+            params("/static/*path", "/static/css/site.css")
+        );
+        assert_eq!(pairs(&[("path", "")]), params("/static/*path", "/static/"));
+        assert_eq!(pairs(&[("path", "")]), params("/static/*path", "/static"));
+        assert_eq!(pairs(&[]), params("/*", "/anything/at/all"));
+        assert_eq!(None, params("/static/*path", "/other/x"));
+
+        //segments are decoded after splitting, so an encoded / stays in its segment
+        assert_eq!(
+            pairs(&[("id", "a/b")]),
+            params("/users/:id", "/users/a%2Fb")
+        );
+        assert_eq!(
+            pairs(&[("id", "a b")]),
+            params("/users/:id", "/users/a%20b")
+        );
+        assert_eq!(pairs(&[]), params("/caf\u{e9}", "/caf%C3%A9"));
+        assert_eq!(
+            pairs(&[("path", "css/a b.css")]),
+            params("/static/*path", "/static/css/a%20b.css")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "wildcard before the end")]
+    fn wildcard_must_be_last() {
+        Router::new().get("/*path/edit", |_, _| Response::new(200));
+    }
+
+    #[test]
+    fn dispatch_and_errors() {
+        let router = Router::new()
+            .get("/users/:id", |_, params| {
+                Response::new(200).body(format!("get {}", &params["id"]))
+            })
+            .delete("/users/:id", |_, params| {
+                Response::new(200).body(format!("delete {}", &params["id"]))
+            })
+            .route("PATCH", "/users/:id", |_, _| Response::new(204))
+            .get("/users/me", |_, _| Response::new(200).body("shadowed"));
+
+        assert_eq!(
+            b"get me".to_vec(),
+            router.handle(&request("GET", "/users/me")).body
+        );
+        assert_eq!(
+            b"delete 3".to_vec(),
+            router.handle(&request("DELETE", "/users/3")).body
+        );
+        assert_eq!(
+            b"get 3".to_vec(),
+            router.handle(&request("HEAD", "/users/3")).body
+        );
+        assert_eq!(
+            b"get a/b".to_vec(),
+            router.handle(&request("GET", "/users/a%2Fb")).body
+        );
+        assert_eq!(404, router.handle(&request("GET", "/nowhere")).status);
+        let router = router.not_found(|request, _| Response::new(404).body(request.path.clone()));
+        assert_eq!(
+            b"/nowhere".to_vec(),
+            router.handle(&request("GET", "/nowhere")).body
+        );
+
+        let response = router.handle(&request("POST", "/users/3"));
+        assert_eq!(405, response.status);
+        assert_eq!(
+            vec![(
+                String::from("Allow"),
+                String::from("GET, HEAD, DELETE, PATCH")
+            )],
+            response.headers
+        );
+    }
+}
@@ -0,0 +1,629 @@
+//! Serving the files under a document root
+
+use std::{
+    fs::{self, File, Metadata},
+    io::{self, prelude::*, SeekFrom},
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{request::Request, response::Response};
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Serves files from a directory, refusing any path that leads outside it
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_web_server::{Router, StaticFiles};
+/// let files = StaticFiles::new("public").unwrap().listings(true);
+/// let router = Router::new().get("/static/*path", move |request, params| {
+///     files.serve(request, &params["path"])
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+    index_files: Vec<String>,
+    listings: bool,
+}
+
+impl StaticFiles {
+    /// Serve the files under root, which must be a directory
+    pub fn new(root: impl AsRef<Path>) -> io::Result<StaticFiles> {
+        let root = root.as_ref().canonicalize()?;
+        if !root.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a directory", root.display()),
+            ));
+        }
+
+        Ok(StaticFiles {
+            root,
+            index_files: vec![String::from("index.html"), String::from("index.htm")],
+            listings: false,
+        })
+    }
+
+    /// Files to serve, in order of preference, for a directory. Defaults to
+    /// `index.html` and `index.htm`.
+    pub fn index_files(mut self, names: &[&str]) -> StaticFiles {
+        self.index_files = names.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// Whether to list the contents of directories without an index file
+    /// rather than answer `403 Forbidden`. Off by default.
+    pub fn listings(mut self, listings: bool) -> StaticFiles {
+        self.listings = listings;
+        self
+    }
+
+    /// Answer request with the file at path, relative to the root. Honours
+    /// `If-None-Match`, `If-Modified-Since`, `Range` and `If-Range`.
+    pub fn serve(&self, request: &Request, path: &str) -> Response {
+        if request.method != "GET" && request.method != "HEAD" {
+            return Response::new(405).header("Allow", "GET, HEAD");
+        }
+
+        let Some(relative) = safe_relative_path(path) else {
+            return Response::new(403);
+        };
+        let full_path = match self.inside_root(&self.root.join(relative)) {
+            Ok(full_path) => full_path,
+            Err(response) => return response,
+        };
+
+        if full_path.is_dir() {
+            //relative links in an index or listing only work from a path ending in /
+            if !request.path.ends_with('/') {
+                let mut location = format!("{}/", percent_encode(&request.path));
+                if let Some(query) = &request.query {
+                    location = format!("{location}?{query}");
+                }
+                return Response::new(301).header("Location", location);
+            }
+            for name in &self.index_files {
+                let index = full_path.join(name);
+                if index.is_file() {
+                    return match self.inside_root(&index) {
+                        Ok(index) => serve_file(request, &index),
+                        Err(response) => response,
+                    };
+                }
+            }
+            return match self.listings {
+                true => listing(&request.path, &full_path),
+                false => Response::new(403),
+            };
+        }
+
+        serve_file(request, &full_path)
+    }
+
+    /// path with symlinks resolved, or the response refusing it if that leads
+    /// out of the root, as a symlink may
+    fn inside_root(&self, path: &Path) -> Result<PathBuf, Response> {
+        let full_path = path.canonicalize().map_err(|err| error_response(&err))?;
+        match full_path.starts_with(&self.root) {
+            true => Ok(full_path),
+            false => Err(Response::new(403)),
+        }
+    }
+}
+
+/// path as a relative path of plain names, or None if it has `..` or
+/// anything else that could lead out of the directory it's joined to
+fn safe_relative_path(path: &str) -> Option<PathBuf> {
+    if path.contains(['\0', '\\']) {
+        return None;
+    }
+
+    let mut relative = PathBuf::new();
+    for component in Path::new(path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(relative)
+}
+
+fn error_response(err: &io::Error) -> Response {
+    match err.kind() {
+        io::ErrorKind::NotFound | io::ErrorKind::NotADirectory => Response::new(404),
+        io::ErrorKind::PermissionDenied => Response::new(403),
+        _ => {
+            println!("Failed to serve file: {err}");
+            Response::new(500)
+        }
+    }
+}
+
+fn serve_file(request: &Request, path: &Path) -> Response {
+    match try_serve_file(request, path) {
+        Ok(response) => response,
+        Err(err) => error_response(&err),
+    }
+}
+
+fn try_serve_file(request: &Request, path: &Path) -> io::Result<Response> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let len = metadata.len();
+    let modified = metadata.modified().ok();
+    let etag = etag(&metadata);
+
+    let mut response = Response::new(200)
+        .header("Content-Type", mime_type(path))
+        .header("Accept-Ranges", "bytes")
+        .header("ETag", etag.as_str());
+    if let Some(modified) = modified {
+        response = response.header("Last-Modified", http_date(modified));
+    }
+
+    if !modified_since(request, &etag, modified) {
+        response.status = 304;
+        return Ok(response);
+    }
+    if request.method == "HEAD" {
+        return Ok(response.header("Content-Length", len.to_string()));
+    }
+
+    let range = request
+        .header("Range")
+        .filter(|_| range_applies(request, &etag, modified));
+    let (start, end) = match range.map(|range| parse_range(range, len)) {
+        Some(Some(Ok((start, end)))) => {
+            response.status = 206;
+            response = response.header("Content-Range", format!("bytes {start}-{end}/{len}"));
+            (start, end + 1)
+        }
+        Some(Some(Err(()))) => {
+            return Ok(Response::new(416).header("Content-Range", format!("bytes */{len}")));
+        }
+        //ranges we don't handle, like several at once, get the whole file
+        Some(None) | None => (0, len),
+    };
+
+    file.seek(SeekFrom::Start(start))?;
+    Ok(response.stream(file, end - start))
+}
+
+/// A validator that changes whenever the file's size or modification time does
+fn etag(metadata: &Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!(
+        "\"{:x}-{:x}.{:x}\"",
+        metadata.len(),
+        modified.as_secs(),
+        modified.subsec_nanos()
+    )
+}
+
+/// Whether the client's cached copy, if it says it has one, is out of date.
+/// `If-None-Match` takes precedence over `If-Modified-Since`.
+fn modified_since(request: &Request, etag: &str, modified: Option<SystemTime>) -> bool {
+    if let Some(tags) = request.header("If-None-Match") {
+        return !tags.split(',').map(str::trim).any(|tag| {
+            //weak comparison, so W/"x" matches "x"
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        });
+    }
+
+    match (
+        request
+            .header("If-Modified-Since")
+            .and_then(parse_http_date),
+        modified,
+    ) {
+        //dates only have whole seconds
+        (Some(since), Some(modified)) => truncate_to_secs(modified) > since,
+        _ => true,
+    }
+}
+
+/// Whether to honour a `Range` header, which `If-Range` allows only while the
+/// file still has the given validator
+fn range_applies(request: &Request, etag: &str, modified: Option<SystemTime>) -> bool {
+    match request.header("If-Range") {
+        None => true,
+        Some(validator) if validator.starts_with('"') => validator == etag,
+        Some(validator) => match (parse_http_date(validator), modified) {
+            (Some(date), Some(modified)) => truncate_to_secs(modified) == date,
+            _ => false,
+        },
+    }
+}
+
+/// The first and last byte of a single `bytes=` range within a file of len
+/// bytes. Returns None for headers we don't handle and `Some(Err(()))` when
+/// the range lies past the end of the file.
+fn parse_range(header: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = header.strip_prefix("bytes=")?.trim();
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = spec.split_once('-')?;
+
+    let (start, end) = match (first.trim(), last.trim()) {
+        ("", "") => return None,
+        //the last n bytes
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            if suffix == 0 || len == 0 {
+                return Some(Err(()));
+            }
+            (len.saturating_sub(suffix), len - 1)
+        }
+        (first, "") => (first.parse().ok()?, len.saturating_sub(1)),
+        (first, last) => {
+            let (first, last): (u64, u64) = (first.parse().ok()?, last.parse().ok()?);
+            if last < first {
+                return None;
+            }
+            (first, last.min(len.saturating_sub(1)))
+        }
+    };
+
+    match start < len {
+        true => Some(Ok((start, end))),
+        false => Some(Err(())),
+    }
+}
+
+/// The Content-Type for a file, guessed from its extension
+pub(crate) fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" | "md" | "rs" | "toml" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "pdf" => "application/pdf",
+        "wasm" => "application/wasm",
+        "mp4" => "video/mp4",
+        "mp3" => "audio/mpeg",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// An HTML page linking to each entry of dir, sorted by name
+fn listing(request_path: &str, dir: &Path) -> Response {
+    let mut names: Vec<String> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                match entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                    true => format!("{name}/"),
+                    false => name,
+                }
+            })
+            .collect(),
+        Err(err) => return error_response(&err),
+    };
+    names.sort();
+
+    let title = escape_html(request_path);
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n  <head>\n    <meta charset=\"UTF-8\" />\n    \
+         <title>Index of {title}</title>\n  </head>\n  <body>\n    <h1>Index of {title}</h1>\n    <ul>\n"
+    );
+    if request_path != "/" {
+        page.push_str("      <li><a href=\"../\">../</a></li>\n");
+    }
+    for name in names {
+        page.push_str(&format!(
+            "      <li><a href=\"{}\">{}</a></li>\n",
+            percent_encode(&name),
+            escape_html(&name)
+        ));
+    }
+    page.push_str("    </ul>\n  </body>\n</html>\n");
+
+    Response::new(200)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(page)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Escape everything but unreserved characters and `/` for use in a link
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// Format time as an HTTP date, like `Sun, 06 Nov 1994 08:49:37 GMT`
+pub(crate) fn http_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+    let days = (secs / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    let seconds = secs % 86400;
+
+    format!(
+        "{}, {day:02} {} {year} {:02}:{:02}:{:02} GMT",
+        DAYS[((days + 4) % 7) as usize],
+        MONTHS[month as usize - 1],
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Parse an HTTP date in the preferred `Sun, 06 Nov 1994 08:49:37 GMT` format
+pub(crate) fn parse_http_date(date: &str) -> Option<SystemTime> {
+    let parts: Vec<&str> = date.split_whitespace().collect();
+    let [_, day, month, year, time, "GMT"] = parts[..] else {
+        return None;
+    };
+
+    let day: u32 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|name| *name == month)? as u32 + 1;
+    let year: i64 = year.parse().ok()?;
+    let mut time = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (Some(Some(hours)), Some(Some(minutes)), Some(Some(seconds)), None) =
+        (time.next(), time.next(), time.next(), time.next())
+    else {
+        return None;
+    };
+    if !(1..=31).contains(&day) || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(days * 86400 + hours * 3600 + minutes * 60 + seconds))
+}
+
+//conversions between days since 1970-01-01 and dates, from
+//https://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(path: &str, headers: &[(&str, &str)]) -> Request {
+        let mut raw = format!("GET {path} HTTP/1.1\r\nHost: x\r\n");
+        for (name, value) in headers {
+            raw.push_str(&format!("{name}: {value}\r\n"));
+        }
+        raw.push_str("\r\n");
+        Request::read_from(&mut raw.as_bytes()).unwrap().unwrap()
+    }
+
+    fn header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+        response
+            .headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The body of response as sent, streamed or not
+    fn body(response: Response) -> String {
+        let mut written = Vec::new();
+        response.write_to(&mut written).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        let (_, body) = written.split_once("\r\n\r\n").unwrap();
+        body.to_string()
+    }
+
+    fn docroot() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("frogs.txt"), "0123456789").unwrap();
+        fs::create_dir(dir.path().join("pond")).unwrap();
+        fs::write(dir.path().join("pond/lily <pad>.css"), "p {}").unwrap();
+        fs::create_dir(dir.path().join("site")).unwrap();
+        fs::write(dir.path().join("site/index.html"), "<h1>site</h1>").unwrap();
+        dir
+    }
+
+    fn serve(files: &StaticFiles, path: &str, headers: &[(&str, &str)]) -> Response {
+        let request = request(path, headers);
+        files.serve(&request, &request.path)
+    }
+
+    #[test]
+    fn files_and_directories() {
+        let dir = docroot();
+        let files = StaticFiles::new(dir.path()).unwrap();
+
+        let response = serve(&files, "/frogs.txt", &[]);
+        assert_eq!(200, response.status);
+        assert_eq!(
+            Some("text/plain; charset=utf-8"),
+            header(&response, "Content-Type")
+        );
+        assert!(header(&response, "Last-Modified").is_some_and(|date| date.ends_with(" GMT")));
+        assert_eq!("0123456789", body(response));
+
+        let response = serve(&files, "/site/", &[]);
+        assert_eq!("<h1>site</h1>", body(response));
+        let response = serve(&files, "/site?x=1", &[]);
+        assert_eq!(
+            (301, Some("/site/?x=1")),
+            (response.status, header(&response, "Location"))
+        );
+
+        assert_eq!(403, serve(&files, "/pond/", &[]).status);
+        let files = files.listings(true);
+        let response = serve(&files, "/pond/", &[]);
+        let page = String::from_utf8(response.body).unwrap();
+        assert!(page.contains("<a href=\"lily%20%3Cpad%3E.css\">lily &lt;pad&gt;.css</a>"));
+
+        assert_eq!(404, serve(&files, "/toads.txt", &[]).status);
+        assert_eq!(404, serve(&files, "/frogs.txt/more", &[]).status);
+    }
+
+    #[test]
+    fn traversal_is_refused() {
+        let dir = docroot();
+        let files = StaticFiles::new(dir.path().join("pond")).unwrap();
+
+        assert_eq!(403, serve(&files, "/../frogs.txt", &[]).status);
+        assert_eq!(403, serve(&files, "/%2e%2e/frogs.txt", &[]).status);
+        assert_eq!(403, serve(&files, "/..%5cfrogs.txt", &[]).status);
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.path().join("frogs.txt"), dir.path().join("pond/out"))
+                .unwrap();
+            assert_eq!(403, serve(&files, "/out", &[]).status);
+            //nor may an index file
+            fs::create_dir(dir.path().join("pond/d")).unwrap();
+            std::os::unix::fs::symlink(
+                dir.path().join("frogs.txt"),
+                dir.path().join("pond/d/index.html"),
+            )
+            .unwrap();
+            assert_eq!(403, serve(&files, "/d/", &[]).status);
+        }
+    }
+
+    #[test]
+    fn conditional_requests() {
+        let dir = docroot();
+        let files = StaticFiles::new(dir.path()).unwrap();
+        let response = serve(&files, "/frogs.txt", &[]);
+        let etag = header(&response, "ETag").unwrap();
+        let modified = header(&response, "Last-Modified").unwrap();
+
+        let response = serve(&files, "/frogs.txt", &[("If-None-Match", etag)]);
+        assert_eq!((304, 0), (response.status, response.body.len()));
+        let weak = format!("\"other\", W/{etag}");
+        assert_eq!(
+            304,
+            serve(&files, "/frogs.txt", &[("If-None-Match", &weak)]).status
+        );
+        assert_eq!(
+            200,
+            serve(&files, "/frogs.txt", &[("If-None-Match", "\"other\"")]).status
+        );
+        assert_eq!(
+            304,
+            serve(&files, "/frogs.txt", &[("If-Modified-Since", modified)]).status
+        );
+        let old = "Sun, 06 Nov 1994 08:49:37 GMT";
+        assert_eq!(
+            200,
+            serve(&files, "/frogs.txt", &[("If-Modified-Since", old)]).status
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let dir = docroot();
+        let files = StaticFiles::new(dir.path()).unwrap();
+        let range = |range: &str| {
+            let response = serve(&files, "/frogs.txt", &[("Range", range)]);
+            let content_range = header(&response, "Content-Range").map(str::to_string);
+            (response.status, content_range, body(response))
+        };
+
+        let partial = |content_range: &str, body: &str| {
+            (206, Some(content_range.to_string()), body.to_string())
+        };
+        assert_eq!(partial("bytes 2-4/10", "234"), range("bytes=2-4"));
+        assert_eq!(partial("bytes 7-9/10", "789"), range("bytes=7-"));
+        assert_eq!(partial("bytes 7-9/10", "789"), range("bytes=-3"));
+        assert_eq!(partial("bytes 8-9/10", "89"), range("bytes=8-100"));
+        assert_eq!(
+            (416, Some(String::from("bytes */10")), String::new()),
+            range("bytes=10-")
+        );
+        assert_eq!(200, range("bytes=0-1,4-5").0);
+        assert_eq!(200, range("lines=1-2").0);
+
+        let stale = serve(
+            &files,
+            "/frogs.txt",
+            &[("Range", "bytes=0-0"), ("If-Range", "\"old\"")],
+        );
+        assert_eq!(200, stale.status);
+    }
+
+    #[test]
+    fn http_dates() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!("Sun, 06 Nov 1994 08:49:37 GMT", http_date(time));
+        assert_eq!(Some(time), parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert_eq!(
+            "Thu, 29 Feb 2024 00:00:00 GMT",
+            http_date(UNIX_EPOCH + Duration::from_secs(1709164800))
+        );
+        assert_eq!(None, parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"));
+    }
+}
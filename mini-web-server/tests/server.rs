@@ -0,0 +1,201 @@
+use mini_web_server::{Config, Response, Router, Server, ServerHandle};
+use std::{
+    io::{self, prelude::*, BufReader},
+    net::{SocketAddr, TcpStream},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+struct Running {
+    address: SocketAddr,
+    handle: ServerHandle,
+    thread: JoinHandle<io::Result<()>>,
+}
+
+impl Running {
+    /// Shut the server down and wait for run to return, giving how long that took
+    fn stop(self) -> Duration {
+        let start = Instant::now();
+        self.handle.shutdown();
+        self.thread.join().unwrap().unwrap();
+        start.elapsed()
+    }
+}
+
+fn start(config: Config) -> Running {
+    let router = Router::new()
+        .get("/", |_, _| Response::new(200).body("hello"))
+        .get("/slow/:millis", |_, params| {
+            thread::sleep(Duration::from_millis(params["millis"].parse().unwrap()));
+            Response::new(200).body("done")
+        });
+    let server = Server::bind(
+        Config {
+            port: 0,
+            thread_size: 2,
+            ..config
+        },
+        router,
+    )
+    .unwrap();
+
+    Running {
+        address: server.local_addr(),
+        handle: server.handle(),
+        thread: thread::spawn(move || server.run()),
+    }
+}
+
+/// Read one response, returning its head and body
+fn read_response(reader: &mut impl BufRead) -> (String, String) {
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" || line.is_empty() {
+            break;
+        }
+        head.push_str(&line);
+    }
+
+    let len: usize = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .map_or(0, |len| len.parse().unwrap());
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).unwrap();
+    (head, String::from_utf8(body).unwrap())
+}
+
+fn send(stream: &mut TcpStream, path: &str) {
+    write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+}
+
+#[test]
+fn start_and_stop() {
+    let server = start(Config::default());
+    let address = server.address;
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    send(&mut stream, "/");
+    let (head, body) = read_response(&mut BufReader::new(&stream));
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert_eq!("hello", body);
+    drop(stream);
+
+    assert!(server.stop() < Duration::from_secs(2));
+    assert!(TcpStream::connect(address).is_err());
+}
+
+#[test]
+fn kept_alive_connections_answer_promptly() {
+    let server = start(Config::default());
+    let mut stream = TcpStream::connect(server.address).unwrap();
+    //send writes each request in pieces, which mustn't be held back either
+    stream.set_nodelay(true).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    //each response goes out whole, not held back waiting on an acknowledgement
+    let start = Instant::now();
+    for _ in 0..50 {
+        send(&mut stream, "/");
+        assert_eq!("hello", read_response(&mut reader).1);
+    }
+    assert!(start.elapsed() < Duration::from_secs(1));
+    server.stop();
+}
+
+#[test]
+fn requests_in_flight_are_answered() {
+    let server = start(Config::default());
+    let mut stream = TcpStream::connect(server.address).unwrap();
+    send(&mut stream, "/slow/500");
+    thread::sleep(Duration::from_millis(100));
+
+    let address = server.address;
+    let stopping = thread::spawn(move || server.stop());
+    thread::sleep(Duration::from_millis(100));
+    //the port closes as soon as shutdown starts, not once the last request is answered
+    assert!(TcpStream::connect(address).is_err());
+    let mut reader = BufReader::new(&stream);
+    let (head, body) = read_response(&mut reader);
+    assert!(head.contains("Connection: close\r\n"));
+    assert_eq!("done", body);
+    //and the server closes the connection rather than wait for another request
+    assert_eq!(0, reader.read(&mut [0; 1]).unwrap());
+    stopping.join().unwrap();
+}
+
+#[test]
+fn queued_connections_are_answered() {
+    let server = start(Config::default());
+    //both workers are busy, so the third connection waits its turn
+    let busy: Vec<TcpStream> = (0..2)
+        .map(|_| {
+            let mut stream = TcpStream::connect(server.address).unwrap();
+            send(&mut stream, "/slow/500");
+            stream
+        })
+        .collect();
+    let mut queued = TcpStream::connect(server.address).unwrap();
+    send(&mut queued, "/");
+    thread::sleep(Duration::from_millis(100));
+
+    let stopping = thread::spawn(move || server.stop());
+    for stream in busy.iter().chain([&queued]) {
+        let (head, _) = read_response(&mut BufReader::new(stream));
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Connection: close\r\n"));
+    }
+    stopping.join().unwrap();
+}
+
+#[test]
+fn requests_arriving_at_shutdown_are_answered() {
+    let server = start(Config::default());
+    let mut stream = TcpStream::connect(server.address).unwrap();
+    send(&mut stream, "/");
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    assert_eq!("hello", read_response(&mut reader).1);
+
+    //the next request has started to arrive when the server is told to stop
+    write!(stream, "GET / HTTP/1.1\r\nHo").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    let stopping = thread::spawn(move || server.stop());
+    thread::sleep(Duration::from_millis(100));
+    write!(stream, "st: localhost\r\n\r\n").unwrap();
+
+    let (head, body) = read_response(&mut reader);
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(head.contains("Connection: close\r\n"));
+    assert_eq!("hello", body);
+    stopping.join().unwrap();
+}
+
+#[test]
+fn idle_connections_dont_hold_up_shutdown() {
+    let server = start(Config {
+        keep_alive: Duration::from_secs(30),
+        ..Config::default()
+    });
+    let mut stream = TcpStream::connect(server.address).unwrap();
+    send(&mut stream, "/");
+    let mut reader = BufReader::new(&stream);
+    assert_eq!("hello", read_response(&mut reader).1);
+
+    assert!(server.stop() < Duration::from_secs(2));
+    assert_eq!(0, reader.read(&mut [0; 1]).unwrap());
+}
+
+#[test]
+fn shutdown_gives_up_after_its_timeout() {
+    let server = start(Config {
+        shutdown_timeout: Duration::from_millis(200),
+        ..Config::default()
+    });
+    let mut stream = TcpStream::connect(server.address).unwrap();
+    send(&mut stream, "/slow/5000");
+    thread::sleep(Duration::from_millis(100));
+
+    assert!(server.stop() < Duration::from_secs(2));
+}
@@ -0,0 +1,94 @@
+//! Throughput of the library search functions over large generated corpora.
+//! Run with `cargo bench`; criterion writes its reports to `target/criterion`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use minigrepr::{search, search_case_insenstive};
+
+//size of each generated corpus
+const CORPUS_LEN: usize = 16 * 1024 * 1024;
+
+//words the generated log lines are made of
+const WORDS: &str = "request handled worker thread connection timeout INFO DEBUG user session \
+    cache miss hit latency ms bytes sent received queue retry backoff upstream status 200 404 \
+    path /api/v1/items GET";
+
+/// Log-like lines of pseudo random words, with `needle` on every `every`th line.
+/// The generator is seeded so every run searches the same text.
+fn corpus(needle: &str, every: usize) -> String {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (state >> 33) as usize
+    };
+
+    let words: Vec<&str> = WORDS.split_whitespace().collect();
+    let mut contents = String::with_capacity(CORPUS_LEN + 256);
+    let mut line = 0;
+    while contents.len() < CORPUS_LEN {
+        line += 1;
+        for _ in 0..4 + next() % 12 {
+            contents.push_str(words[next() % words.len()]);
+            contents.push(' ');
+        }
+        if line % every == 0 {
+            contents.push_str(needle);
+        }
+        contents.push('\n');
+    }
+    contents
+}
+
+/// The line by line scans the search functions used before, for comparison
+fn search_per_line<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    contents
+        .lines()
+        .filter(|line| line.contains(query))
+        .collect()
+}
+
+fn search_per_line_lowercase<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    let query = query.to_lowercase();
+    contents
+        .lines()
+        .filter(|line| line.to_lowercase().contains(&query))
+        .collect()
+}
+
+fn bench_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+    group.throughput(Throughput::Bytes(CORPUS_LEN as u64));
+    group.sample_size(20);
+
+    //a needle on one line in ten thousand, like an error in a log, and one on every tenth line
+    for (name, every) in [("rare", 10_000), ("common", 10)] {
+        let contents = corpus("segfault at 0x0", every);
+
+        group.bench_with_input(
+            BenchmarkId::new("search", name),
+            &contents,
+            |b, contents| b.iter(|| search("segfault", contents)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("per_line", name),
+            &contents,
+            |b, contents| b.iter(|| search_per_line("segfault", contents)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("search_case_insenstive", name),
+            &contents,
+            |b, contents| b.iter(|| search_case_insenstive("SEGFAULT", contents)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("per_line_lowercase", name),
+            &contents,
+            |b, contents| b.iter(|| search_per_line_lowercase("SEGFAULT", contents)),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_search);
+criterion_main!(benches);
@@ -0,0 +1,84 @@
+//! Transparent decompression of gzip, zstd, bzip2 and xz files (`-z`)
+
+use std::io::{self, BufRead, BufReader};
+
+use bzip2::read::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use xz2::bufread::XzDecoder;
+
+//magic bytes each format's files start with
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// Wrap reader in a streaming decoder if its contents start with the magic
+/// bytes of a compression format we know, or hand it back untouched otherwise.
+/// Concatenated streams, as left behind by `cat a.gz b.gz`, are all decoded.
+pub(crate) fn decoder<'a>(mut reader: Box<dyn BufRead + 'a>) -> io::Result<Box<dyn BufRead + 'a>> {
+    let head = reader.fill_buf()?;
+
+    Ok(if head.starts_with(GZIP_MAGIC) {
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+    } else if head.starts_with(ZSTD_MAGIC) {
+        Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?))
+    } else if head.starts_with(BZIP2_MAGIC) {
+        Box::new(BufReader::new(MultiBzDecoder::new(reader)))
+    } else if head.starts_with(XZ_MAGIC) {
+        Box::new(BufReader::new(XzDecoder::new_multi_decoder(reader)))
+    } else {
+        reader
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    const TEXT: &str = "The frog sat on a log.\nFROGS are green.\n";
+
+    fn decode(bytes: Vec<u8>) -> String {
+        let mut decoded = String::new();
+        decoder(Box::new(io::Cursor::new(bytes)))
+            .unwrap()
+            .read_to_string(&mut decoded)
+            .unwrap();
+        decoded
+    }
+
+    #[test]
+    fn decodes_each_format() {
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        gzip.write_all(TEXT.as_bytes()).unwrap();
+        assert_eq!(TEXT, decode(gzip.finish().unwrap()));
+
+        let zstd = zstd::encode_all(TEXT.as_bytes(), 0).unwrap();
+        assert_eq!(TEXT, decode(zstd));
+
+        let mut bzip2 = bzip2::write::BzEncoder::new(Vec::new(), Default::default());
+        bzip2.write_all(TEXT.as_bytes()).unwrap();
+        assert_eq!(TEXT, decode(bzip2.finish().unwrap()));
+
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(TEXT.as_bytes()).unwrap();
+        assert_eq!(TEXT, decode(xz.finish().unwrap()));
+    }
+
+    #[test]
+    fn concatenated_gzip_streams() {
+        let mut bytes = Vec::new();
+        for line in TEXT.split_inclusive('\n') {
+            let mut gzip = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+            gzip.write_all(line.as_bytes()).unwrap();
+            bytes.extend(gzip.finish().unwrap());
+        }
+        assert_eq!(TEXT, decode(bytes));
+    }
+
+    #[test]
+    fn plain_text_is_left_alone() {
+        assert_eq!(TEXT, decode(TEXT.as_bytes().to_vec()));
+        assert_eq!("", decode(Vec::new()));
+    }
+}
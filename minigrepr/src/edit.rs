@@ -0,0 +1,127 @@
+//! Rewriting files in place with `--replace --in-place`
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use tempfile::NamedTempFile;
+
+use crate::matcher::Pattern;
+use crate::printer::Printer;
+use crate::{is_binary, trim_line_ending, Config, Matcher, STDIN, STDIN_NAME};
+
+/// Replace the matches in filename, writing the new contents to a temporary
+/// file next to it and renaming that over the original, so readers only ever
+/// see the old or the new file. Lines that aren't valid UTF-8 are left alone.
+pub(crate) fn edit_in_place(
+    config: &Config,
+    pattern: &Pattern,
+    filename: &str,
+    printer: &mut Printer<impl Write>,
+) -> io::Result<()> {
+    if filename == STDIN {
+        printer.error(STDIN_NAME, "can't be edited in place");
+        return Ok(());
+    }
+
+    match rewrite(config, pattern, Path::new(filename)) {
+        Ok(Some((replaced, bytes_searched))) => {
+            printer.begin(filename);
+            printer.end(replaced, bytes_searched)
+        }
+        //binary files are skipped, just as when searching
+        Ok(None) => Ok(()),
+        Err(err) => {
+            printer.error(filename, err);
+            Ok(())
+        }
+    }
+}
+
+/// Rewrite path, returning how many lines changed and how many bytes were read,
+/// or `None` for a binary file
+fn rewrite(config: &Config, pattern: &Pattern, path: &Path) -> io::Result<Option<(usize, usize)>> {
+    let template = config.replace.as_deref().unwrap_or_default();
+    let mut reader = BufReader::new(File::open(path)?);
+    if is_binary(reader.fill_buf()?) {
+        return Ok(None);
+    }
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let temp = NamedTempFile::new_in(dir)?;
+    let mut writer = BufWriter::new(temp);
+
+    let mut buf = Vec::new();
+    let mut replaced = 0;
+    let mut bytes_searched = 0;
+    loop {
+        buf.clear();
+        let len = reader.read_until(b'\n', &mut buf)?;
+        if len == 0 {
+            break;
+        }
+        bytes_searched += len;
+
+        let text = trim_line_ending(&buf);
+        let ending = &buf[text.len()..];
+        match std::str::from_utf8(text) {
+            Ok(text) if pattern.is_match(text) => {
+                replaced += 1;
+                writer.write_all(pattern.replace_all(text, template).0.as_bytes())?;
+                writer.write_all(ending)?;
+            }
+            _ => writer.write_all(&buf)?,
+        }
+    }
+
+    //nothing to do, so leave the file and its timestamps untouched
+    if replaced == 0 {
+        return Ok(Some((0, bytes_searched)));
+    }
+
+    let temp = writer.into_inner().map_err(|err| err.into_error())?;
+    temp.as_file().set_permissions(fs::metadata(path)?.permissions())?;
+    temp.as_file().sync_all()?;
+    temp.persist(path).map_err(|err| err.error)?;
+
+    Ok(Some((replaced, bytes_searched)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrites_only_matching_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.ini");
+        fs::write(&path, b"name = frog\r\nbad = \xff frog\nsize = 3\n").unwrap();
+        let path_arg = path.to_str().unwrap();
+
+        let args = [
+            "minigrepr",
+            "-E",
+            "--replace",
+            "$1: $2",
+            "--in-place",
+            r"^(\w+) = (\w+)$",
+            path_arg,
+        ];
+        let config = Config::build(args.iter().map(|arg| arg.to_string())).unwrap();
+        let pattern = Pattern::build(&config).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, false);
+        edit_in_place(&config, &pattern, path_arg, &mut printer).unwrap();
+
+        assert_eq!(
+            b"name: frog\r\nbad = \xff frog\nsize: 3\n".to_vec(),
+            fs::read(&path).unwrap()
+        );
+        assert_eq!(1, printer.stats().searches_with_match);
+        assert_eq!(0, printer.stats().errors);
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+        assert!(printer.into_inner().is_empty());
+    }
+}
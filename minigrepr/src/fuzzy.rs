@@ -0,0 +1,205 @@
+//! Approximate matching within a Levenshtein edit distance (`--fuzzy K`)
+
+use std::error::Error;
+use std::ops::Range;
+
+/// Queries that match any substring of a line within `max_distance` edits
+#[derive(Debug, Clone)]
+pub(crate) struct Fuzzy {
+    queries: Vec<Vec<char>>,
+    max_distance: usize,
+    ignore_case: bool,
+}
+
+/// One cell of the dynamic programming table: the fewest edits turning the
+/// query prefix into some substring ending here, and the byte offset that
+/// substring starts at
+#[derive(Clone, Copy)]
+struct Cell {
+    distance: usize,
+    start: usize,
+}
+
+impl Fuzzy {
+    pub(crate) fn new(
+        queries: &[String],
+        max_distance: usize,
+        ignore_case: bool,
+    ) -> Result<Fuzzy, Box<dyn Error>> {
+        let mut fuzzy = Fuzzy {
+            queries: Vec::with_capacity(queries.len()),
+            max_distance,
+            ignore_case,
+        };
+        for query in queries {
+            //K edits or more turn the query into the empty string, which matches everywhere
+            let chars: Vec<char> = query.chars().map(|c| fuzzy.fold(c)).collect();
+            if chars.len() <= max_distance {
+                return Err(format!(
+                    "fuzzy distance {max_distance} must be less than the length of {query:?}"
+                )
+                .into());
+            }
+            fuzzy.queries.push(chars);
+        }
+        Ok(fuzzy)
+    }
+
+    /// The leftmost approximate match in line starting at or after byte offset
+    /// `start`, and its distance from the query it matched
+    pub(crate) fn find_at(&self, line: &str, start: usize) -> Option<(Range<usize>, usize)> {
+        self.queries
+            .iter()
+            .filter_map(|query| self.find_query_at(query, line, start))
+            .min_by_key(|(found, distance)| (found.start, *distance))
+    }
+
+    /// The fewest edits turning any of the queries into text
+    pub(crate) fn distance(&self, text: &str) -> usize {
+        let text: Vec<char> = text.chars().map(|c| self.fold(c)).collect();
+        self.queries
+            .iter()
+            .map(|query| levenshtein(query, &text))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Sellers' algorithm: the edit distance table of query against line, where
+    /// a match may start anywhere for free. Each column is one character of the
+    /// line, and the last row says how close the best substring ending there is.
+    fn find_query_at(
+        &self,
+        query: &[char],
+        line: &str,
+        start: usize,
+    ) -> Option<(Range<usize>, usize)> {
+        let mut column: Vec<Cell> = (0..=query.len())
+            .map(|distance| Cell { distance, start })
+            .collect();
+        let mut next = column.clone();
+        let mut best: Option<(Range<usize>, usize)> = None;
+
+        for (offset, c) in line[start..].char_indices() {
+            let end = start + offset + c.len_utf8();
+            let c = self.fold(c);
+
+            next[0] = Cell {
+                distance: 0,
+                start: end,
+            };
+            for (i, &q) in query.iter().enumerate() {
+                let substitute = Cell {
+                    distance: column[i].distance + usize::from(q != c),
+                    ..column[i]
+                };
+                let skip_query = Cell {
+                    distance: next[i].distance + 1,
+                    ..next[i]
+                };
+                let skip_line = Cell {
+                    distance: column[i + 1].distance + 1,
+                    ..column[i + 1]
+                };
+                //among equally close alignments prefer the shortest substring
+                next[i + 1] = [substitute, skip_query, skip_line]
+                    .into_iter()
+                    .min_by_key(|cell| (cell.distance, usize::MAX - cell.start))
+                    .unwrap();
+            }
+            std::mem::swap(&mut column, &mut next);
+
+            let last = column[query.len()];
+            match &best {
+                //keep extending the first match for as long as that keeps it as close
+                Some((_, distance)) if last.distance > *distance => break,
+                Some(_) => best = Some((last.start..end, last.distance)),
+                None if last.distance <= self.max_distance => {
+                    best = Some((last.start..end, last.distance))
+                }
+                None => {}
+            }
+        }
+
+        best
+    }
+
+    fn fold(&self, c: char) -> char {
+        if !self.ignore_case {
+            return c;
+        }
+        //characters that lowercase to several, like the Turkish `İ`, are kept as they are
+        let mut lower = c.to_lowercase();
+        match (lower.next(), lower.next()) {
+            (Some(folded), None) => folded,
+            _ => c,
+        }
+    }
+}
+
+/// The number of single character insertions, deletions and substitutions
+/// turning a into b
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, &ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fuzzy(query: &str, max_distance: usize) -> Fuzzy {
+        Fuzzy::new(&[query.to_string()], max_distance, false).unwrap()
+    }
+
+    #[test]
+    fn finds_typos() {
+        let line = "connection refsued by peer";
+        let found = fuzzy("refused", 2).find_at(line, 0);
+        assert_eq!(Some((11..18, 2)), found);
+        assert_eq!(None, fuzzy("refused", 1).find_at(line, 0));
+        assert_eq!(
+            Some((0..4, 0)),
+            fuzzy("frog", 1).find_at("frog and frgo", 0)
+        );
+        assert_eq!(
+            Some((9..12, 1)),
+            fuzzy("frog", 1).find_at("frog and fro", 4)
+        );
+    }
+
+    #[test]
+    fn extends_matches_while_as_close() {
+        //"colo" is already within 1 of "color", and so is "colour"
+        assert_eq!(Some((4..10, 1)), fuzzy("color", 1).find_at("the colour", 0));
+        assert_eq!(Some((4..9, 0)), fuzzy("color", 1).find_at("the colors", 0));
+        assert_eq!(Some((4..9, 0)), fuzzy("color", 1).find_at("the color", 0));
+    }
+
+    #[test]
+    fn distances() {
+        assert_eq!(
+            3,
+            levenshtein(
+                &['k', 'i', 't', 't', 'e', 'n'],
+                &['s', 'i', 't', 't', 'i', 'n', 'g']
+            )
+        );
+        let fuzzy = Fuzzy::new(&[String::from("Frog")], 1, true).unwrap();
+        assert_eq!(0, fuzzy.distance("FROG"));
+        assert_eq!(Some((0..4, 1)), fuzzy.find_at("FROd", 0));
+    }
+
+    #[test]
+    fn distance_must_leave_something_to_match() {
+        assert!(Fuzzy::new(&[String::from("log")], 3, false).is_err());
+    }
+}
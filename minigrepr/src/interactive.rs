@@ -0,0 +1,454 @@
+//! Browsing matches in a terminal UI (`--interactive`)
+//!
+//! All of the state lives in `App`, which turns key events into actions and is
+//! drawn by `draw`, so the UI can be driven with scripted keys and rendered to
+//! a test backend without a terminal.
+
+use std::cell::Cell;
+use std::error::Error;
+use std::io::{self, BufRead, IsTerminal};
+use std::process::Command;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{self, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+
+use crate::matcher::Pattern;
+use crate::{
+    input_files, is_binary, max_count, open_input, select_lines, Config, Match, MyResult, Status,
+    STDIN,
+};
+
+//stop collecting matches past this many, so a short query on a big tree stays responsive
+const MAX_HITS: usize = 10_000;
+
+//rows a page up or down moves the selection by
+const PAGE: usize = 10;
+
+/// What the event loop should do after a key
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Action {
+    Continue,
+    Quit,
+    /// Open path in the editor at line
+    Open {
+        path: String,
+        line: usize,
+    },
+}
+
+/// A matching line and the file it was found in
+struct Hit {
+    file: usize,
+    found: Match,
+}
+
+pub(crate) struct App {
+    config: Config,
+    files: Vec<String>,
+    query: String,
+    hits: Vec<Hit>,
+    list: ListState,
+    //files that couldn't be read, and why the query doesn't compile if it doesn't
+    unreadable: usize,
+    error: Option<String>,
+}
+
+impl App {
+    /// An app searching files, starting from the pattern in config
+    pub(crate) fn new(config: Config, files: Vec<String>) -> App {
+        let query = config.patterns.first().cloned().unwrap_or_default();
+        let mut app = App {
+            config,
+            files,
+            query,
+            hits: Vec::new(),
+            list: ListState::default(),
+            unreadable: 0,
+            error: None,
+        };
+        app.refresh();
+        app
+    }
+
+    pub(crate) fn handle_key(&mut self, key: KeyEvent) -> Action {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('c') if ctrl => return Action::Quit,
+            KeyCode::Enter => {
+                if let Some(hit) = self.list.selected().and_then(|index| self.hits.get(index)) {
+                    return Action::Open {
+                        path: self.files[hit.file].clone(),
+                        line: hit.found.line_number,
+                    };
+                }
+            }
+            KeyCode::Down => self.move_selection(1),
+            KeyCode::Char('n') if ctrl => self.move_selection(1),
+            KeyCode::Up => self.move_selection(-1),
+            KeyCode::Char('p') if ctrl => self.move_selection(-1),
+            KeyCode::PageDown => self.move_selection(PAGE as isize),
+            KeyCode::PageUp => self.move_selection(-(PAGE as isize)),
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.refresh();
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.query.push(c);
+                self.refresh();
+            }
+            _ => {}
+        }
+        Action::Continue
+    }
+
+    fn move_selection(&mut self, by: isize) {
+        if let Some(selected) = self.list.selected() {
+            let last = self.hits.len() - 1;
+            self.list
+                .select(Some(selected.saturating_add_signed(by).min(last)));
+        }
+    }
+
+    /// Search the files again for the current query, with the same matching
+    /// rules as a normal run
+    fn refresh(&mut self) {
+        self.hits.clear();
+        self.unreadable = 0;
+        self.error = None;
+        self.list.select(None);
+        if self.query.is_empty() {
+            return;
+        }
+
+        self.config.patterns = vec![self.query.clone()];
+        let pattern = match Pattern::build(&self.config) {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                self.error = Some(err.to_string());
+                return;
+            }
+        };
+
+        for (file, filename) in self.files.iter().enumerate() {
+            //the hits still to collect count down like -m, so the last file stops at the limit
+            let remaining = MAX_HITS - self.hits.len();
+            let max_count =
+                Some(max_count(&self.config).map_or(remaining, |max| max.min(remaining)));
+            let mut reader = match open_input(&self.config, filename) {
+                Ok(reader) => reader,
+                Err(_) => {
+                    self.unreadable += 1;
+                    continue;
+                }
+            };
+            if reader.fill_buf().map_or(true, is_binary) {
+                continue;
+            }
+            let hits = &mut self.hits;
+            let searched = select_lines(
+                &self.config,
+                &pattern,
+                reader,
+                max_count,
+                |line, selected, spans, _| {
+                    if selected {
+                        let found = Match {
+                            line_number: line.number,
+                            byte_offset: line.byte_offset,
+                            line: line.text.to_string(),
+                            spans: spans.to_vec(),
+                        };
+                        hits.push(Hit { file, found });
+                    }
+                    Ok(false)
+                },
+            );
+            if searched.map_or(true, |searched| searched.error.is_some()) {
+                self.unreadable += 1;
+            }
+            if self.hits.len() == MAX_HITS {
+                break;
+            }
+        }
+
+        if !self.hits.is_empty() {
+            self.list.select(Some(0));
+        }
+    }
+
+    fn status(&self) -> String {
+        if let Some(err) = &self.error {
+            return err.clone();
+        }
+        let mut files: Vec<usize> = self.hits.iter().map(|hit| hit.file).collect();
+        files.dedup();
+        let mut status = format!(
+            "{}{} {} in {} of {} files",
+            self.hits.len(),
+            if self.hits.len() == MAX_HITS { "+" } else { "" },
+            if self.hits.len() == 1 {
+                "match"
+            } else {
+                "matches"
+            },
+            files.len(),
+            self.files.len(),
+        );
+        if self.unreadable > 0 {
+            status.push_str(&format!(", {} unreadable", self.unreadable));
+        }
+        status.push_str(" | Enter opens, Esc quits");
+        status
+    }
+}
+
+pub(crate) fn draw(frame: &mut Frame, app: &mut App) {
+    let [query_area, hits_area, status_area] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(1),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let query = Paragraph::new(app.query.as_str()).block(Block::bordered().title(" Pattern "));
+    frame.render_widget(query, query_area);
+    frame.set_cursor_position((
+        query_area.x + 1 + app.query.chars().count() as u16,
+        query_area.y + 1,
+    ));
+
+    let path_style = Style::new().fg(Color::Magenta);
+    let number_style = Style::new().fg(Color::Green);
+    let match_style = Style::new().fg(Color::Red).add_modifier(Modifier::BOLD);
+    let items: Vec<ListItem> = app
+        .hits
+        .iter()
+        .map(|hit| {
+            let found = &hit.found;
+            let mut spans = vec![
+                Span::styled(app.files[hit.file].as_str(), path_style),
+                Span::raw(":"),
+                Span::styled(found.line_number.to_string(), number_style),
+                Span::raw(":"),
+            ];
+            let mut written = 0;
+            for span in found.spans.iter().filter(|span| !span.is_empty()) {
+                spans.push(Span::raw(&found.line[written..span.start]));
+                spans.push(Span::styled(&found.line[span.clone()], match_style));
+                written = span.end;
+            }
+            spans.push(Span::raw(&found.line[written..]));
+            ListItem::new(text::Line::from(spans))
+        })
+        .collect();
+    let hits = List::new(items)
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    frame.render_stateful_widget(hits, hits_area, &mut app.list);
+
+    let status = Paragraph::new(app.status()).style(Style::new().add_modifier(Modifier::DIM));
+    frame.render_widget(status, status_area);
+}
+
+/// Run the terminal UI until the user quits. Matched if there were any
+/// matches for the last query.
+pub(crate) fn run(config: Config) -> MyResult<Status> {
+    if !io::stdout().is_terminal() {
+        return Err("--interactive needs a terminal".into());
+    }
+    if config.files.iter().any(|file| file == STDIN) {
+        return Err("--interactive can't search standard input; name files or use -r".into());
+    }
+    if config.patterns.len() > 1 {
+        return Err("--interactive takes a single pattern".into());
+    }
+
+    let walk_errors = Cell::new(0);
+    let files = input_files(&config, &walk_errors)?.collect();
+    let mut app = App::new(config, files);
+
+    let mut terminal = ratatui::init();
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        loop {
+            terminal.draw(|frame| draw(frame, &mut app))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match app.handle_key(key) {
+                Action::Continue => {}
+                Action::Quit => return Ok(()),
+                Action::Open { path, line } => {
+                    //hand the terminal over to the editor, then take it back
+                    ratatui::restore();
+                    let status = editor_command(&editor(), &path, line).status();
+                    terminal = ratatui::init();
+                    terminal.clear()?;
+                    if let Err(err) = status {
+                        app.error = Some(format!("can't start editor: {err}"));
+                    }
+                }
+            }
+        }
+    })();
+    ratatui::restore();
+    result?;
+
+    Ok(match app.hits.is_empty() {
+        true => Status::NoMatch,
+        false => Status::Match,
+    })
+}
+
+/// The user's editor: `$VISUAL`, then `$EDITOR`, then vi
+fn editor() -> String {
+    std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"))
+}
+
+/// The command opening path at line, in the `editor +line path` form vi, emacs,
+/// nano and most other editors understand. editor may carry its own arguments,
+/// like `emacsclient -t`.
+fn editor_command(editor: &str, path: &str, line: usize) -> Command {
+    let mut words = editor.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or("vi"));
+    command.args(words).arg(format!("+{line}")).arg(path);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use std::fs;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn type_keys(app: &mut App, keys: &str) {
+        for c in keys.chars() {
+            assert_eq!(Action::Continue, app.handle_key(key(KeyCode::Char(c))));
+        }
+    }
+
+    /// The rendered screen, one string per row
+    fn screen(app: &mut App) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(80, 8)).unwrap();
+        terminal.draw(|frame| draw(frame, app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    fn app(args: &[&str]) -> (tempfile::TempDir, App) {
+        let dir = tempfile::tempdir().unwrap();
+        let frogs = dir.path().join("frogs.txt");
+        let toads = dir.path().join("toads.txt");
+        fs::write(&frogs, "The frog sat on a log.\nFROGS are green.\n").unwrap();
+        fs::write(&toads, "A toad.\nNo frogs.\n").unwrap();
+
+        let files = [&frogs, &toads].map(|path| path.to_str().unwrap().to_string());
+        let args = std::iter::once("minigrepr")
+            .chain(args.iter().copied())
+            .map(String::from)
+            .chain(files.clone());
+        let config = Config::build(args).unwrap();
+        (dir, App::new(config, files.to_vec()))
+    }
+
+    #[test]
+    fn refines_the_query_live() {
+        let (_dir, mut app) = app(&["-i", "--interactive", "fro"]);
+        assert_eq!(3, app.hits.len());
+
+        type_keys(&mut app, "gs");
+        assert_eq!(2, app.hits.len());
+        assert_eq!(Action::Continue, app.handle_key(key(KeyCode::Backspace)));
+        type_keys(&mut app, " s");
+        assert_eq!(1, app.hits.len());
+        assert_eq!("The frog sat on a log.", app.hits[0].found.line);
+    }
+
+    #[test]
+    fn searches_like_a_normal_run() {
+        let lines = |args: &[&str]| -> Vec<String> {
+            let (_dir, app) = app(args);
+            app.hits.into_iter().map(|hit| hit.found.line).collect()
+        };
+        assert_eq!(
+            vec!["The frog sat on a log.", "No frogs."],
+            lines(&["--interactive", "-i", "-m", "1", "frog"])
+        );
+        assert_eq!(
+            vec!["The frog sat on a log.", "FROGS are green."],
+            lines(&["--interactive", "-U", "log.\nFROGS"])
+        );
+    }
+
+    #[test]
+    fn opens_the_selected_match() {
+        let (_dir, mut app) = app(&["--interactive", "frog"]);
+        let toads = app.files[1].clone();
+
+        app.handle_key(key(KeyCode::Down));
+        app.handle_key(key(KeyCode::Down));
+        assert_eq!(
+            Action::Open {
+                path: toads,
+                line: 2
+            },
+            app.handle_key(key(KeyCode::Enter))
+        );
+        assert_eq!(Action::Quit, app.handle_key(key(KeyCode::Esc)));
+    }
+
+    #[test]
+    fn renders_matches_and_status() {
+        let (_dir, mut app) = app(&["--interactive", "-w", "frog"]);
+        let rows = screen(&mut app);
+        assert!(rows[1].starts_with("│frog "));
+        assert!(rows[3].starts_with("> "));
+        assert!(rows[3].ends_with("frogs.txt:1:The frog sat on a log."));
+        assert_eq!("1 match in 1 of 2 files | Enter opens, Esc quits", rows[7]);
+
+        type_keys(&mut app, "sy");
+        assert_eq!(
+            "0 matches in 0 of 2 files | Enter opens, Esc quits",
+            screen(&mut app)[7]
+        );
+    }
+
+    #[test]
+    fn invalid_regex_is_reported() {
+        let (_dir, mut app) = app(&["--interactive", "-E", "fr"]);
+        type_keys(&mut app, "(");
+        assert!(app.hits.is_empty());
+        assert!(screen(&mut app)[7].starts_with("regex parse error"));
+        assert_eq!(Action::Continue, app.handle_key(key(KeyCode::Enter)));
+    }
+
+    #[test]
+    fn editor_commands() {
+        let command = editor_command("emacsclient -t", "src/lib.rs", 12);
+        assert_eq!("emacsclient", command.get_program());
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(vec!["-t", "+12", "src/lib.rs"], args);
+    }
+}
@@ -2,55 +2,960 @@
 //! 
 //! `minigrepr` is a collection of search functions for searching a given query in a file
 
+use std::cell::Cell;
 use std::env;
 use std::error::Error;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::iter;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Instant;
 
+mod decompress;
+mod edit;
+mod fuzzy;
+mod interactive;
+mod matcher;
+mod parallel;
+mod printer;
+mod searcher;
+
+use clap::{App, Arg, ErrorKind};
+use ignore::overrides::OverrideBuilder;
+use ignore::types::TypesBuilder;
+use ignore::WalkBuilder;
+use memchr::memmem;
+use regex::{Regex, RegexBuilder};
+use regex_syntax::ast;
+
+use matcher::Pattern;
+use printer::Printer;
+
+pub use searcher::{
+    CaseInsensitiveMatcher, FuzzyMatcher, LiteralMatcher, Match, Matcher, Matches, RegexMatcher,
+    Searcher,
+};
+
+//file name that reads from stdin, and how stdin is named in output
+const STDIN: &str = "-";
+const STDIN_NAME: &str = "(standard input)";
+
+//number of leading bytes inspected for a NUL byte when deciding whether a file is binary
+const BINARY_SNIFF_LEN: usize = 8192;
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+#[derive(Debug)]
 pub struct Config {
-    pub query: String,
-    pub file_path: String,
+    pub patterns: Vec<String>,
+    pub files: Vec<String>,
     pub ignore_case: bool,
+    pub regex: bool,
+    pub word_regexp: bool,
+    pub line_regexp: bool,
+    pub multiline: bool,
+    pub fuzzy: Option<usize>,
+    pub recursive: bool,
+    pub search_zip: bool,
+    pub types: Vec<String>,
+    pub types_not: Vec<String>,
+    pub globs: Vec<String>,
+    pub invert_match: bool,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    pub count: bool,
+    pub files_with_matches: bool,
+    pub files_without_match: bool,
+    pub max_count: Option<usize>,
+    pub only_matching: bool,
+    pub quiet: bool,
+    pub replace: Option<String>,
+    pub in_place: bool,
+    pub threads: usize,
+    pub color: bool,
+    pub json: bool,
+    pub interactive: bool,
 }
 
 impl Config {
-    pub fn build(mut args:  impl Iterator<Item = String> ) -> Result<Config, &'static str> {
-        args.next();
+    /// Parse the command line arguments, program name first.
+    ///
+    /// `-i`/`-s` win over the `IGNORE_CASE` environment variable, which only
+    /// decides case sensitivity when neither flag is given.
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Config, clap::Error> {
+        Config::build_with_env(args, env::var("IGNORE_CASE").is_ok())
+    }
 
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("No search string query provided")
-        };
+    /// `build`, with whether `IGNORE_CASE` is set passed in rather than read
+    /// from the process environment
+    fn build_with_env(
+        args: impl Iterator<Item = String>,
+        ignore_case_env: bool,
+    ) -> Result<Config, clap::Error> {
+        let matches = App::new("minigrepr")
+            .version(env!("CARGO_PKG_VERSION"))
+            .author("Kiran S <codehub.kirans@gmail.com>")
+            .about("Rust grep program")
+            .arg(
+                Arg::with_name("query")
+                    .value_name("PATTERN")
+                    .help("Search string query, taken as the first FILE when -e or -f is given")
+                    .required_unless_one(&["regexp", "pattern_file"]),
+            )
+            .arg(
+                Arg::with_name("files")
+                    .value_name("FILE")
+                    .help("Input file(s), - for stdin [default: - or . when recursive]")
+                    .multiple(true),
+            )
+            .arg(
+                Arg::with_name("regexp")
+                    .short("e")
+                    .long("regexp")
+                    .value_name("PATTERN")
+                    .help("Search for PATTERN; repeat to match any of several patterns")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .allow_hyphen_values(true),
+            )
+            .arg(
+                Arg::with_name("pattern_file")
+                    .short("f")
+                    .long("file")
+                    .value_name("FILE")
+                    .help("Search for the patterns in FILE, one per line")
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .arg(
+                Arg::with_name("ignore_case")
+                    .short("i")
+                    .long("ignore-case")
+                    .help("Case insensitive search")
+                    .overrides_with_all(&["case_sensitive", "smart_case"]),
+            )
+            .arg(
+                Arg::with_name("case_sensitive")
+                    .short("s")
+                    .long("case-sensitive")
+                    .help("Case sensitive search, even if IGNORE_CASE is set")
+                    .overrides_with_all(&["ignore_case", "smart_case"]),
+            )
+            .arg(
+                Arg::with_name("smart_case")
+                    .short("S")
+                    .long("smart-case")
+                    .help("Case insensitive search unless the pattern has an uppercase letter")
+                    .overrides_with_all(&["ignore_case", "case_sensitive"]),
+            )
+            .arg(
+                Arg::with_name("regex")
+                    .short("E")
+                    .long("regex")
+                    .help("Treat the pattern as a regular expression"),
+            )
+            .arg(
+                Arg::with_name("word_regexp")
+                    .short("w")
+                    .long("word-regexp")
+                    .help("Only match whole words"),
+            )
+            .arg(
+                Arg::with_name("line_regexp")
+                    .short("x")
+                    .long("line-regexp")
+                    .help("Only match whole lines"),
+            )
+            .arg(
+                Arg::with_name("multiline")
+                    .short("U")
+                    .long("multiline")
+                    .help("Let matches span lines, e.g. with \\n in a -E pattern; every line a match touches is selected")
+                    .conflicts_with_all(&["fuzzy", "replace"]),
+            )
+            .arg(
+                Arg::with_name("fuzzy")
+                    .long("fuzzy")
+                    .value_name("K")
+                    .help("Match the pattern within K typos (Levenshtein distance), printing each line's distance")
+                    .validator(validate_count)
+                    .conflicts_with_all(&["regex", "line_regexp"]),
+            )
+            .arg(
+                Arg::with_name("recursive")
+                    .short("r")
+                    .long("recursive")
+                    .help("Search directories recursively"),
+            )
+            .arg(
+                Arg::with_name("type")
+                    .short("t")
+                    .long("type")
+                    .value_name("TYPE")
+                    .help("Only search files of TYPE when recursing, like rust, py or md")
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .arg(
+                Arg::with_name("type_not")
+                    .short("T")
+                    .long("type-not")
+                    .value_name("TYPE")
+                    .help("Don't search files of TYPE when recursing")
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .arg(
+                Arg::with_name("glob")
+                    .short("g")
+                    .long("glob")
+                    .value_name("GLOB")
+                    .help("Only search paths matching GLOB when recursing, or skip them if GLOB starts with !")
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .arg(
+                Arg::with_name("search_zip")
+                    .short("z")
+                    .long("search-zip")
+                    .help("Search the contents of gzip, zstd, bzip2 and xz compressed files")
+                    .conflicts_with("in_place"),
+            )
+            .arg(
+                Arg::with_name("invert_match")
+                    .short("v")
+                    .long("invert-match")
+                    .help("Select non-matching lines"),
+            )
+            .arg(
+                Arg::with_name("line_number")
+                    .short("n")
+                    .long("line-number")
+                    .help("Prefix each line with its line number"),
+            )
+            .arg(
+                Arg::with_name("byte_offset")
+                    .short("b")
+                    .long("byte-offset")
+                    .help("Prefix each line with the byte offset of its start"),
+            )
+            .arg(
+                Arg::with_name("after_context")
+                    .short("A")
+                    .long("after-context")
+                    .value_name("NUM")
+                    .help("Print NUM lines of context after each selected line")
+                    .validator(validate_count),
+            )
+            .arg(
+                Arg::with_name("before_context")
+                    .short("B")
+                    .long("before-context")
+                    .value_name("NUM")
+                    .help("Print NUM lines of context before each selected line")
+                    .validator(validate_count),
+            )
+            .arg(
+                Arg::with_name("context")
+                    .short("C")
+                    .long("context")
+                    .value_name("NUM")
+                    .help("Print NUM lines of context around each selected line")
+                    .validator(validate_count),
+            )
+            .arg(
+                Arg::with_name("count")
+                    .short("c")
+                    .long("count")
+                    .help("Print only a count of selected lines per file"),
+            )
+            .arg(
+                Arg::with_name("files_with_matches")
+                    .short("l")
+                    .long("files-with-matches")
+                    .help("Print only the names of files with selected lines")
+                    .overrides_with_all(&["count", "files_without_match"]),
+            )
+            .arg(
+                Arg::with_name("files_without_match")
+                    .short("L")
+                    .long("files-without-match")
+                    .help("Print only the names of files with no selected lines")
+                    .overrides_with_all(&["count", "files_with_matches"]),
+            )
+            .arg(
+                Arg::with_name("max_count")
+                    .short("m")
+                    .long("max-count")
+                    .value_name("NUM")
+                    .help("Stop reading a file after NUM selected lines")
+                    .validator(validate_count),
+            )
+            .arg(
+                Arg::with_name("only_matching")
+                    .short("o")
+                    .long("only-matching")
+                    .help("Print only the matched parts of lines, each on its own line"),
+            )
+            .arg(
+                Arg::with_name("quiet")
+                    .short("q")
+                    .long("quiet")
+                    .help("Print nothing; exit with status 0 on the first selected line"),
+            )
+            .arg(
+                Arg::with_name("replace")
+                    .long("replace")
+                    .value_name("TEMPLATE")
+                    .help("Print selected lines with every match replaced by TEMPLATE; $1 refers to a capture group with -E")
+                    .allow_hyphen_values(true)
+                    .conflicts_with_all(&["invert_match", "json"]),
+            )
+            .arg(
+                Arg::with_name("in_place")
+                    .long("in-place")
+                    .help("Write the replacements back to the files instead of printing them")
+                    .requires("replace")
+                    .conflicts_with_all(&[
+                        "count",
+                        "files_with_matches",
+                        "files_without_match",
+                        "only_matching",
+                        "quiet",
+                    ]),
+            )
+            .arg(
+                Arg::with_name("threads")
+                    .short("j")
+                    .long("threads")
+                    .value_name("NUM")
+                    .help("Number of files to search at once, 0 for one per CPU [default: 0]")
+                    .validator(validate_count),
+            )
+            .arg(
+                Arg::with_name("color")
+                    .long("color")
+                    .value_name("WHEN")
+                    .help("Highlight matches, file names and line numbers")
+                    .possible_values(&["auto", "always", "never"])
+                    .default_value("auto"),
+            )
+            .arg(
+                Arg::with_name("json")
+                    .long("json")
+                    .help("Print results as JSON lines: begin, match, context, end and summary messages")
+                    .conflicts_with_all(&["count", "files_with_matches", "files_without_match"]),
+            )
+            .arg(
+                Arg::with_name("interactive")
+                    .long("interactive")
+                    .help("Browse matches in a terminal UI, refining the pattern as you type and opening them in $EDITOR")
+                    .conflicts_with_all(&[
+                        "invert_match",
+                        "count",
+                        "files_with_matches",
+                        "files_without_match",
+                        "quiet",
+                        "replace",
+                        "json",
+                    ]),
+            )
+            .get_matches_from_safe(args)?;
 
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err("No file name provided")
+        let regex = matches.is_present("regex");
+
+        //with -e or -f the first positional argument is a file rather than the pattern
+        let mut files = matches.values_of_lossy("files").unwrap_or_default();
+        let mut patterns = matches.values_of_lossy("regexp").unwrap_or_default();
+        for pattern_file in matches.values_of_lossy("pattern_file").unwrap_or_default() {
+            let contents = fs::read_to_string(&pattern_file).map_err(|err| {
+                clap::Error::with_description(&format!("{pattern_file}: {err}"), ErrorKind::Io)
+            })?;
+            patterns.extend(contents.lines().map(String::from));
+        }
+        if let Some(query) = matches.value_of("query") {
+            if matches.is_present("regexp") || matches.is_present("pattern_file") {
+                files.insert(0, query.to_string());
+            } else {
+                patterns.push(query.to_string());
+            }
+        }
+
+        let ignore_case = if matches.is_present("ignore_case") {
+            true
+        } else if matches.is_present("case_sensitive") {
+            false
+        } else if matches.is_present("smart_case") {
+            !patterns.iter().any(|pattern| has_uppercase(pattern, regex))
+        } else {
+            ignore_case_env
         };
 
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
-        println!("Ignore case environment variable is set |{ignore_case}|");
+        //-A and -B override -C for their side
+        let count_of = |name| matches.value_of(name).map(|num: &str| num.parse().unwrap());
+        let context = count_of("context").unwrap_or(0);
+        let recursive = matches.is_present("recursive");
 
         Ok(Config {
-            query,
-            file_path,
+            patterns,
+            files: if files.is_empty() {
+                vec![String::from(if recursive { "." } else { STDIN })]
+            } else {
+                files
+            },
             ignore_case,
+            regex,
+            word_regexp: matches.is_present("word_regexp"),
+            line_regexp: matches.is_present("line_regexp"),
+            multiline: matches.is_present("multiline"),
+            fuzzy: count_of("fuzzy"),
+            recursive,
+            search_zip: matches.is_present("search_zip"),
+            types: matches.values_of_lossy("type").unwrap_or_default(),
+            types_not: matches.values_of_lossy("type_not").unwrap_or_default(),
+            globs: matches.values_of_lossy("glob").unwrap_or_default(),
+            invert_match: matches.is_present("invert_match"),
+            line_number: matches.is_present("line_number"),
+            byte_offset: matches.is_present("byte_offset"),
+            before_context: count_of("before_context").unwrap_or(context),
+            after_context: count_of("after_context").unwrap_or(context),
+            count: matches.is_present("count"),
+            files_with_matches: matches.is_present("files_with_matches"),
+            files_without_match: matches.is_present("files_without_match"),
+            max_count: count_of("max_count"),
+            only_matching: matches.is_present("only_matching"),
+            quiet: matches.is_present("quiet"),
+            replace: matches.value_of("replace").map(String::from),
+            in_place: matches.is_present("in_place"),
+            threads: match count_of("threads") {
+                None | Some(0) => thread::available_parallelism().map_or(1, |n| n.get()),
+                Some(threads) => threads,
+            },
+            color: match matches.value_of("color") {
+                Some("always") => true,
+                Some("never") => false,
+                _ => io::stdout().is_terminal(),
+            },
+            json: matches.is_present("json"),
+            interactive: matches.is_present("interactive"),
         })
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.file_path)?;
+/// Whether the query has an uppercase letter, for smart case. A regular
+/// expression is parsed so only its literal characters count, and not escapes
+/// or class names like `\W` or `\p{L}`.
+fn has_uppercase(query: &str, regex: bool) -> bool {
+    if !regex {
+        return query.chars().any(char::is_uppercase);
+    }
+    match ast::parse::Parser::new().parse(query) {
+        Ok(ast) => ast::visit(&ast, UppercaseLiterals(false)).unwrap_or(false),
+        //an invalid pattern fails the search anyway
+        Err(_) => query.chars().any(char::is_uppercase),
+    }
+}
+
+/// Looks for an uppercase literal in a regular expression, inside or outside
+/// of a character class
+struct UppercaseLiterals(bool);
+
+impl ast::Visitor for UppercaseLiterals {
+    type Output = bool;
+    type Err = ();
+
+    fn finish(self) -> Result<bool, ()> {
+        Ok(self.0)
+    }
+
+    fn visit_pre(&mut self, ast: &ast::Ast) -> Result<(), ()> {
+        if let ast::Ast::Literal(literal) = ast {
+            self.0 |= literal.c.is_uppercase();
+        }
+        Ok(())
+    }
+
+    fn visit_class_set_item_pre(&mut self, item: &ast::ClassSetItem) -> Result<(), ()> {
+        match item {
+            ast::ClassSetItem::Literal(literal) => self.0 |= literal.c.is_uppercase(),
+            ast::ClassSetItem::Range(range) => {
+                self.0 |= range.start.c.is_uppercase() || range.end.c.is_uppercase();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn validate_count(num: String) -> Result<(), String> {
+    num.parse::<usize>()
+        .map(|_| ())
+        .map_err(|_| format!("\"{num}\" is not a valid line count"))
+}
+
+/// How a search turned out, which `main` reports as a grep compatible exit status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// A line was selected, or with `-L` a file was listed
+    Match,
+    NoMatch,
+    /// A file could not be searched
+    Error,
+}
+
+impl Status {
+    pub fn code(self) -> i32 {
+        match self {
+            Status::Match => 0,
+            Status::NoMatch => 1,
+            Status::Error => 2,
+        }
+    }
+}
+
+pub fn run(config: Config) -> MyResult<Status> {
+    if config.interactive {
+        return interactive::run(config);
+    }
+
+    let pattern = Pattern::build(&config)?;
+    let with_filename = config.recursive || config.files.len() > 1;
+    let walk_errors = Cell::new(0);
+    let files = input_files(&config, &walk_errors)?;
+
+    let start = Instant::now();
+    let stdout = io::stdout();
+
+    //a single file streams straight to stdout rather than being buffered by a worker,
+    //and -q has to stop at the first file with a match
+    let stats = if config.threads == 1 || !with_filename || config.quiet {
+        let mut printer = Printer::new(stdout.lock(), &config, with_filename);
+        for file in files {
+            search_file(&config, &pattern, &file, &mut printer)?;
+            if config.quiet && printer.stats().searches_with_match > 0 {
+                break;
+            }
+        }
+        printer.stats()
+    } else {
+        parallel::search_files(&config, &pattern, files, with_filename, stdout.lock())?
+    };
+
+    //-q prints nothing, not even the summary
+    if config.json && !config.quiet {
+        printer::write_summary(&mut stdout.lock(), stats, start.elapsed())?;
+    }
 
-    let results = if config.ignore_case {
-        search_case_insenstive(&config.query, &contents)
+    let matched = if config.files_without_match {
+        stats.searches > stats.searches_with_match
     } else {
-        search(&config.query, &contents)
+        stats.searches_with_match > 0
+    };
+    let failed = stats.errors + walk_errors.get() > 0;
+
+    Ok(if matched && (config.quiet || !failed) {
+        Status::Match
+    } else if failed {
+        Status::Error
+    } else {
+        Status::NoMatch
+    })
+}
+
+/// Open a file for buffered reading, with `-` meaning stdin
+fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
+    match filename {
+        STDIN => Ok(Box::new(BufReader::new(io::stdin()))),
+        _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
+    }
+}
+
+/// The files named in config, with directories walked when searching recursively.
+/// Walks are set up front so an unknown file type or a bad glob is reported
+/// before any output.
+fn input_files<'a>(
+    config: &Config,
+    walk_errors: &'a Cell<usize>,
+) -> Result<impl Iterator<Item = String> + 'a, ignore::Error> {
+    let mut sources: Vec<Box<dyn Iterator<Item = String>>> = Vec::new();
+    for file in &config.files {
+        if config.recursive && file != STDIN {
+            let paths = walk(file, config, walk_errors)?;
+            sources.push(Box::new(paths.map(|path| path.display().to_string())));
+        } else {
+            sources.push(Box::new(iter::once(file.clone())));
+        }
+    }
+    Ok(sources.into_iter().flatten())
+}
+
+/// Open filename for searching; with `-z` compressed files are recognised by
+/// their contents, whatever they are named, and decompressed as they are read
+fn open_input(config: &Config, filename: &str) -> MyResult<Box<dyn BufRead>> {
+    let reader = open(filename)?;
+    match config.search_zip {
+        true => Ok(decompress::decoder(reader)?),
+        false => Ok(reader),
+    }
+}
+
+/// Search a single file and print its results, reporting unreadable files on stderr
+fn search_file(
+    config: &Config,
+    pattern: &Pattern,
+    filename: &str,
+    printer: &mut Printer<impl Write>,
+) -> io::Result<()> {
+    let name = match filename {
+        STDIN => STDIN_NAME,
+        _ => filename,
+    };
+
+    if config.in_place {
+        return edit::edit_in_place(config, pattern, filename, printer);
+    }
+
+    match open_input(config, filename) {
+        Err(err) => {
+            printer.error(name, err);
+            Ok(())
+        }
+        Ok(reader) => search_reader(config, pattern, name, reader, printer),
+    }
+}
+
+/// Search a reader and print its results
+fn search_reader(
+    config: &Config,
+    pattern: &Pattern,
+    name: &str,
+    mut reader: impl BufRead,
+    printer: &mut Printer<impl Write>,
+) -> io::Result<()> {
+    let head = match reader.fill_buf() {
+        Ok(head) => head,
+        Err(err) => {
+            printer.error(name, err);
+            return Ok(());
+        }
     };
+    if is_binary(head) {
+        return Ok(());
+    }
 
-    for line in results {
-        println!("{line}");
+    printer.begin(name);
+    let searched = select_lines(
+        config,
+        pattern,
+        reader,
+        max_count(config),
+        |line, selected, spans, distances| {
+            printer.line(line, selected, spans, distances)?;
+            //past the maximum only the trailing context of the last selected line is still wanted
+            Ok(printer.after_context_pending())
+        },
+    )?;
+    if let Some(err) = searched.error {
+        printer.error(name, err);
     }
+    printer.end(searched.selected, searched.bytes_read)
+}
 
-    Ok(())
+/// How a search of a reader went
+struct Searched {
+    selected: usize,
+    bytes_read: usize,
+    /// The error reading stopped at, if it didn't reach the end
+    error: Option<io::Error>,
+}
+
+/// Pass each line of reader in order to each, with whether it's selected, the
+/// spans to show and their fuzzy distances, honouring `-v`, `--replace` and
+/// max_count. This is the search both a normal run and `--interactive` do.
+/// Once max_count lines are selected, lines are only passed on while each
+/// returns true, to finish a trailing context.
+fn select_lines(
+    config: &Config,
+    pattern: &Pattern,
+    reader: impl BufRead,
+    max_count: Option<usize>,
+    each: impl FnMut(Line, bool, &[Range<usize>], &[usize]) -> io::Result<bool>,
+) -> io::Result<Searched> {
+    if config.multiline {
+        return search_buffer(config, pattern, reader, max_count, each);
+    }
+    search_by_line(config, pattern, reader, max_count, each)
+}
+
+/// Search a reader line by line, holding only the current line in memory.
+/// Invalid UTF-8 is replaced rather than rejected.
+fn search_by_line(
+    config: &Config,
+    pattern: &Pattern,
+    reader: impl BufRead,
+    max_count: Option<usize>,
+    mut each: impl FnMut(Line, bool, &[Range<usize>], &[usize]) -> io::Result<bool>,
+) -> io::Result<Searched> {
+    let searcher = Searcher::new(pattern)
+        .invert_match(config.invert_match)
+        .max_count(max_count);
+    //only lines that matched have anything to highlight, pick out or measure
+    let wants_spans = config.color
+        || config.json
+        || config.only_matching
+        || config.interactive
+        || config.fuzzy.is_some();
+    let mut lines = searcher.lines(reader, wants_spans);
+
+    let mut more = false;
+    let mut error = None;
+    loop {
+        if lines.reached_max() && !more {
+            break;
+        }
+
+        let mut line = match lines.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                error = Some(err);
+                break;
+            }
+        };
+        let distances: Vec<usize> = line
+            .spans
+            .iter()
+            .filter_map(|span| pattern.distance(&line.text[span.clone()]))
+            .collect();
+        if let (Some(template), true) = (&config.replace, line.selected) {
+            let (replaced, replaced_spans) = pattern.replace_all(&line.text, template);
+            line.text = replaced.into();
+            line.spans = replaced_spans;
+        }
+
+        let searched = Line {
+            number: line.number,
+            byte_offset: line.byte_offset,
+            text: &line.text,
+        };
+        more = each(searched, line.selected, &line.spans, &distances)?;
+    }
+    Ok(Searched {
+        selected: lines.selected(),
+        bytes_read: lines.bytes_read(),
+        error,
+    })
+}
+
+/// Search all of reader at once for `-U`, so matches can run across lines. Every
+/// line a match touches is selected, with the part of the match on it as its span.
+fn search_buffer(
+    config: &Config,
+    pattern: &Pattern,
+    mut reader: impl BufRead,
+    max_count: Option<usize>,
+    mut each: impl FnMut(Line, bool, &[Range<usize>], &[usize]) -> io::Result<bool>,
+) -> io::Result<Searched> {
+    let mut bytes = Vec::new();
+    if let Err(err) = reader.read_to_end(&mut bytes) {
+        return Ok(Searched {
+            selected: 0,
+            bytes_read: 0,
+            error: Some(err),
+        });
+    }
+    let contents = String::from_utf8_lossy(&bytes);
+    let lines: Vec<Line> = lines(&contents).collect();
+
+    let mut matched = vec![false; lines.len()];
+    let mut line_spans = vec![Vec::new(); lines.len()];
+    for found in pattern.find_iter(&contents) {
+        let covered = covered_lines(&lines, &found);
+        for index in covered {
+            let line = &lines[index];
+            matched[index] = true;
+            //the part of the match on this line, leaving out the line ending
+            let start = found.start.max(line.byte_offset) - line.byte_offset;
+            let end = found.end.min(line.byte_offset + line.text.len()) - line.byte_offset;
+            if start < end {
+                line_spans[index].push(start..end);
+            }
+        }
+    }
+
+    let mut selected = 0;
+    let mut more = false;
+    for (index, line) in lines.into_iter().enumerate() {
+        let reached_max = max_count.is_some_and(|max| selected >= max);
+        if reached_max && !more {
+            break;
+        }
+        let is_selected = !reached_max && matched[index] != config.invert_match;
+        if is_selected {
+            selected += 1;
+        }
+        let spans = match is_selected && !config.invert_match {
+            true => std::mem::take(&mut line_spans[index]),
+            false => Vec::new(),
+        };
+        more = each(line, is_selected, &spans, &[])?;
+    }
+    Ok(Searched {
+        selected,
+        bytes_read: bytes.len(),
+        error: None,
+    })
+}
+
+/// Indexes of the lines that the byte range found in the whole contents touches
+fn covered_lines(lines: &[Line], found: &Range<usize>) -> Range<usize> {
+    //an empty match in empty contents is on no line at all
+    if lines.is_empty() {
+        return 0..0;
+    }
+    let line_at = |offset: usize| lines.partition_point(|line| line.byte_offset <= offset) - 1;
+    let first = line_at(found.start);
+    //an empty match, or one ending with a line ending, doesn't reach into the next line
+    let last = line_at(found.end.saturating_sub(1).max(found.start));
+    first..last + 1
+}
+
+/// How many lines to select before moving on to the next file; one is enough
+/// to settle -q, -l and -L
+fn max_count(config: &Config) -> Option<usize> {
+    if config.quiet || config.files_with_matches || config.files_without_match {
+        Some(1)
+    } else {
+        config.max_count
+    }
+}
+
+/// Strip a trailing `\n` or `\r\n`, the same line endings `str::lines` removes
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    match line.strip_suffix(b"\n") {
+        Some(line) => line.strip_suffix(b"\r").unwrap_or(line),
+        None => line,
+    }
+}
+
+/// Every regular file under root, skipping hidden files and anything matched by
+/// `.gitignore` or `.ignore` rules, in a stable order. Directories that can't be
+/// read are reported on stderr and counted in errors.
+fn walk<'a>(
+    root: &str,
+    config: &Config,
+    errors: &'a Cell<usize>,
+) -> Result<impl Iterator<Item = PathBuf> + 'a, ignore::Error> {
+    let mut types = TypesBuilder::new();
+    types.add_defaults();
+    for name in &config.types {
+        types.select(name);
+    }
+    for name in &config.types_not {
+        types.negate(name);
+    }
+
+    //globs are matched against paths relative to the root being walked
+    let mut overrides = OverrideBuilder::new(root);
+    for glob in &config.globs {
+        overrides.add(glob)?;
+    }
+
+    let walk = WalkBuilder::new(root)
+        .require_git(false)
+        .types(types.build()?)
+        .overrides(overrides.build()?)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+
+    Ok(walk
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                eprintln!("{err}");
+                errors.set(errors.get() + 1);
+                None
+            }
+        })
+        .filter(|entry| entry.file_type().is_some_and(|file_type| file_type.is_file()))
+        .map(|entry| entry.into_path()))
+}
+
+/// A file is treated as binary if a NUL byte shows up near its start, the same heuristic git and grep use
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0)
+}
+
+/// A line of searched contents along with where it was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'a> {
+    /// Line number, starting at 1
+    pub number: usize,
+    /// Offset in bytes of the start of the line from the start of the contents
+    pub byte_offset: usize,
+    /// The line without its line ending
+    pub text: &'a str,
+}
+
+/// Split contents into lines the same way `str::lines` does, keeping track of
+/// each line's number and byte offset
+///
+/// # Examples
+///
+/// ```
+/// use minigrepr::{lines, Line};
+/// let contents = "Rust:\r\nsafe, fast, productive.\n";
+/// assert_eq!(
+///     lines(contents).collect::<Vec<_>>(),
+///     vec![
+///         Line { number: 1, byte_offset: 0, text: "Rust:" },
+///         Line { number: 2, byte_offset: 7, text: "safe, fast, productive." },
+///     ]
+/// );
+/// ```
+pub fn lines(contents: &str) -> impl Iterator<Item = Line<'_>> {
+    let mut byte_offset = 0;
+
+    contents
+        .split_inclusive('\n')
+        .enumerate()
+        .map(move |(index, raw)| {
+            let text = match raw.strip_suffix('\n') {
+                Some(text) => text.strip_suffix('\r').unwrap_or(text),
+                None => raw,
+            };
+            let line = Line {
+                number: index + 1,
+                byte_offset,
+                text,
+            };
+            byte_offset += raw.len();
+            line
+        })
+}
+
+/// Search the given file contents for lines accepted by `is_match`, returning
+/// each line with its position rather than just its text
+///
+/// # Examples
+///
+/// ```
+/// use minigrepr::search_lines;
+/// let contents = "\
+/// Rust:
+/// safe, fast, productive.
+/// Pick three.
+/// Duct tape.";
+/// let found = search_lines(contents, |line| line.contains("duct"));
+/// assert_eq!(found.len(), 1);
+/// assert_eq!((found[0].number, found[0].byte_offset), (2, 6));
+/// ```
+pub fn search_lines<'a>(contents: &'a str, mut is_match: impl FnMut(&str) -> bool) -> Vec<Line<'a>> {
+    lines(contents).filter(|line| is_match(line.text)).collect()
 }
 
 /// Case sensitive search of query in the given file contents
@@ -79,14 +984,14 @@ pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
     results
     */
 
-    contents
-        .lines()
-        .filter(|line| line.contains(query))
-        .collect()
+    search_with(query, contents, SearchOptions::default())
 }
 
 /// Case insensitive search of query in the given file contents
 ///
+/// Characters are compared using Unicode simple case folding, so `ẞ` finds `ß`
+/// and the `K` Kelvin sign finds `k`, without lowercasing every line.
+///
 /// # Examples
 ///
 /// ```
@@ -101,17 +1006,179 @@ pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
 /// assert_eq!(search_case_insenstive(query, contents), vec!["Rust:", "Trust me."]);
 /// ```
 pub fn search_case_insenstive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
+    let options = SearchOptions {
+        ignore_case: true,
+        ..SearchOptions::default()
+    };
+    search_with(query, contents, options)
+}
+
+/// How `search_with` matches its query, mirroring the `-i`, `-w` and `-x` flags
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// Compare characters by their Unicode simple case folding, as `search_case_insenstive` does
+    pub ignore_case: bool,
+    /// Only match query where it starts and ends at word boundaries, so `err` doesn't match `stderr`
+    pub word_regexp: bool,
+    /// Only match lines that are query in their entirety; this wins over `word_regexp`
+    pub line_regexp: bool,
+}
+
+/// Search of query in the given file contents, matching it as options say
+///
+/// # Examples
+///
+/// ```
+/// use minigrepr::{search_with, SearchOptions};
+/// let contents = "\
+/// stderr: closed
+/// err: code 3
+/// ERR";
+/// let words = SearchOptions { word_regexp: true, ..SearchOptions::default() };
+/// assert_eq!(search_with("err", contents, words), vec!["err: code 3"]);
+/// let lines = SearchOptions { ignore_case: true, line_regexp: true, ..SearchOptions::default() };
+/// assert_eq!(search_with("err", contents, lines), vec!["ERR"]);
+/// ```
+pub fn search_with<'a>(query: &str, contents: &'a str, options: SearchOptions) -> Vec<&'a str> {
+    if options.line_regexp {
+        return match options.ignore_case {
+            true => {
+                let re = RegexBuilder::new(&format!("^{}$", regex::escape(query)))
+                    .case_insensitive(true)
+                    .build()
+                    .expect("an escaped literal is always a valid regular expression");
+                contents.lines().filter(|line| re.is_match(line)).collect()
+            }
+            false => contents.lines().filter(|line| *line == query).collect(),
+        };
+    }
+
+    //memmem scans the whole buffer with SIMD where available, so only lines with a hit get split out
+    let finder = memmem::Finder::new(query);
+    let re = options
+        .ignore_case
+        .then(|| matcher::case_insensitive_literal(query));
+    let find_at = |at: usize| match &re {
+        Some(re) => re.find_at(contents, at).map(|found| found.range()),
+        None => finder
+            .find(&contents.as_bytes()[at..])
+            .map(|offset| at + offset..at + offset + query.len()),
+    };
+
+    lines_with_hits(contents, |mut at| loop {
+        let hit = find_at(at)?;
+        //a line ending isn't a word character, so boundaries in contents are boundaries in the line
+        if !options.word_regexp || matcher::at_word_boundaries(contents, &hit) {
+            return Some(hit);
+        }
+        at = hit.start + matcher::char_len_at(contents, hit.start).max(1);
+        if at > contents.len() {
+            return None;
+        }
+    })
+}
+
+/// The lines of contents, as `str::lines` splits them, that hold a hit. Rather
+/// than testing line by line, `find_at` searches the whole of contents from a byte
+/// offset, and the line around each hit is only worked out once it is found.
+fn lines_with_hits(
+    contents: &str,
+    mut find_at: impl FnMut(usize) -> Option<Range<usize>>,
+) -> Vec<&str> {
+    let bytes = contents.as_bytes();
+    let mut results = Vec::new();
+    let mut at = 0;
+
+    while at <= bytes.len() {
+        let Some(hit) = find_at(at) else {
+            break;
+        };
+        let start = memchr::memrchr(b'\n', &bytes[..hit.start]).map_or(0, |newline| newline + 1);
+        //an empty hit after the final line ending is not on any line
+        if start == bytes.len() {
+            break;
+        }
+        let (end, next) = match memchr::memchr(b'\n', &bytes[hit.start..]) {
+            Some(offset) => {
+                let newline = hit.start + offset;
+                let end = match newline > start && bytes[newline - 1] == b'\r' {
+                    true => newline - 1,
+                    false => newline,
+                };
+                (end, newline + 1)
+            }
+            None => (bytes.len(), bytes.len() + 1),
+        };
+
+        //a hit running into the line ending isn't within the line, but the line may hold another
+        if hit.end <= end {
+            results.push(&contents[start..end]);
+            at = next;
+        } else {
+            at = hit.start + 1;
+        }
+    }
+
+    results
+}
+
+/// Regular expression search over the whole of contents, so a match may span
+/// several lines, as with `-U`. Each match comes back as all of the lines it
+/// touches; `^` and `$` only match at the ends of the contents unless the
+/// pattern turns on multi-line mode with `(?m)`.
+///
+/// # Examples
+///
+/// ```
+/// use minigrepr::search_multiline;
+/// use regex::Regex;
+/// let re = Regex::new(r"(?m)^panicked at .*\n\s+at src/lib\.rs").unwrap();
+/// let contents = "\
+/// panicked at main.rs:3
+///     at src/main.rs:3
+/// panicked at lib.rs:10
+///     at src/lib.rs:10
+/// done";
+/// let found = search_multiline(&re, contents);
+/// assert_eq!(found.len(), 1);
+/// let numbers: Vec<usize> = found[0].iter().map(|line| line.number).collect();
+/// assert_eq!(numbers, vec![3, 4]);
+/// ```
+pub fn search_multiline<'a>(re: &Regex, contents: &'a str) -> Vec<Vec<Line<'a>>> {
+    let lines: Vec<Line> = lines(contents).collect();
 
+    re.find_iter(contents)
+        .map(|found| lines[covered_lines(&lines, &found.range())].to_vec())
+        .filter(|covered| !covered.is_empty())
+        .collect()
+}
+
+/// Regular expression search of a compiled pattern in the given file contents
+///
+/// # Examples
+///
+/// ```
+/// use minigrepr::search_regex;
+/// use regex::Regex;
+/// let re = Regex::new(r"^\w+ \w+\.$").unwrap();
+/// let contents = "\
+/// Rust:
+/// safe, fast, productive.
+/// Pick three.
+/// Trust me.";
+/// assert_eq!(search_regex(&re, contents), vec!["Pick three.", "Trust me."]);
+/// ```
+pub fn search_regex<'a>(re: &Regex, contents: &'a str) -> Vec<&'a str> {
     contents
         .lines()
-        .filter(|line| line.to_lowercase().contains(&query))
+        .filter(|line| re.is_match(line))
         .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
 
     #[test]
     fn case_sensitive() {
@@ -134,4 +1201,309 @@ Pick three.
 Trust me.";
         assert_eq!(vec!["Rust:", "Trust me."], search_case_insenstive(query, contents));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn regex() {
+        let re = Regex::new(r"\b(safe|tape)\b").unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+        assert_eq!(vec!["safe, fast, productive.", "Duct tape."], search_regex(&re, contents));
+    }
+
+    //built without IGNORE_CASE, whatever the environment the tests run in
+    fn build(args: &[&str]) -> Config {
+        let args = std::iter::once("minigrepr").chain(args.iter().copied());
+        Config::build_with_env(args.map(String::from), false).unwrap()
+    }
+
+    #[test]
+    fn flags() {
+        let config = build(&["-E", "-r", "-v", "-n", "fr.g", "."]);
+        assert!(config.regex);
+        assert!(config.recursive);
+        assert!(config.invert_match);
+        assert!(config.line_number);
+        assert_eq!(vec!["fr.g"], config.patterns);
+        assert_eq!(vec!["."], config.files);
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let config = build(&["-c", "--", "-n", "poem.txt", "frogs.txt"]);
+        assert!(config.count);
+        assert!(!config.line_number);
+        assert_eq!(vec!["-n"], config.patterns);
+        assert_eq!(vec!["poem.txt", "frogs.txt"], config.files);
+    }
+
+    #[test]
+    fn case_flags_win_over_env() {
+        //the environment is passed in, as other tests build configs at the same time
+        let build_with_env = |args: &[&str], ignore_case_env| {
+            let args = std::iter::once("minigrepr").chain(args.iter().copied());
+            Config::build_with_env(args.map(String::from), ignore_case_env).unwrap()
+        };
+        assert!(build_with_env(&["frog", "poem.txt"], true).ignore_case);
+        assert!(!build_with_env(&["-s", "frog", "poem.txt"], true).ignore_case);
+        assert!(!build_with_env(&["frog", "poem.txt"], false).ignore_case);
+        assert!(build_with_env(&["-i", "frog", "poem.txt"], false).ignore_case);
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let config = build(&["-e", "frog", "--regexp", "-toad", "poem.txt", "frogs.txt"]);
+        assert_eq!(vec!["frog", "-toad"], config.patterns);
+        assert_eq!(vec!["poem.txt", "frogs.txt"], config.files);
+    }
+
+    #[test]
+    fn pattern_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let signatures = dir.path().join("signatures.txt");
+        fs::write(&signatures, "ECONNRESET\nout of memory\n").unwrap();
+        let signatures = signatures.to_str().unwrap();
+
+        let config = build(&["-f", signatures, "-e", "panicked", "app.log"]);
+        assert_eq!(vec!["panicked", "ECONNRESET", "out of memory"], config.patterns);
+        assert_eq!(vec!["app.log"], config.files);
+
+        let missing = ["minigrepr", "-f", "no/such/signatures.txt", "app.log"].map(String::from);
+        assert!(Config::build(missing.into_iter()).is_err());
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(build(&["-S", "frog", "poem.txt"]).ignore_case);
+        assert!(!build(&["-S", "Frog", "poem.txt"]).ignore_case);
+        assert!(!build(&["-S", "-e", "frog", "-e", "Toad", "poem.txt"]).ignore_case);
+        assert!(build(&["-S", "-E", r"\Wfrog\S", "poem.txt"]).ignore_case);
+        assert!(build(&["-S", "-E", r"\p{L}+\b\D", "poem.txt"]).ignore_case);
+        assert!(!build(&["-S", "-E", r"\p{L}+[A-Z]", "poem.txt"]).ignore_case);
+        assert!(!build(&["-S", "-E", r"\x{46}rog|Toad", "poem.txt"]).ignore_case);
+        assert!(!build(&["-S", "-i", "-s", "frog", "poem.txt"]).ignore_case);
+        assert!(build(&["-s", "-S", "frog", "poem.txt"]).ignore_case);
+    }
+
+    #[test]
+    fn whole_buffer_search_agrees_with_line_by_line() {
+        let contents = [
+            "",
+            "\n",
+            "frog",
+            "a frog\nfrog frog\r\n\nno\nlast frog",
+            "crlf\r\nends\r",
+            "trailing\n\n",
+        ];
+        let queries = ["", "frog", "o", "\r", "\n", "g\nf", "og\r"];
+        for contents in contents {
+            for query in queries {
+                let expected: Vec<&str> = contents
+                    .lines()
+                    .filter(|line| line.contains(query))
+                    .collect();
+                assert_eq!(expected, search(query, contents), "{query:?} in {contents:?}");
+            }
+        }
+        assert_eq!(vec!["FROG frog"], search_case_insenstive("fRoG", "toad\nFROG frog\n"));
+    }
+
+    #[test]
+    fn word_and_line_options() {
+        let contents = "stderr: closed\nerr_code 3\nerr: code 3\nfailed (ERR)\nerr\nÉrr";
+        let options = |ignore_case, word_regexp, line_regexp| SearchOptions {
+            ignore_case,
+            word_regexp,
+            line_regexp,
+        };
+        assert_eq!(
+            vec!["err: code 3", "err"],
+            search_with("err", contents, options(false, true, false))
+        );
+        assert_eq!(
+            vec!["err: code 3", "failed (ERR)", "err"],
+            search_with("err", contents, options(true, true, false))
+        );
+        assert_eq!(vec!["err"], search_with("err", contents, options(false, false, true)));
+        assert_eq!(vec!["Érr"], search_with("éRR", contents, options(true, false, true)));
+        //a rejected hit may overlap the one that is a whole word
+        assert_eq!(vec!["aa a"], search_with("a", "aa a", options(false, true, false)));
+    }
+
+    #[test]
+    fn case_insensitive_unicode() {
+        let contents = "Die Straße\nDIE STRASSE\n20 K\n";
+        assert_eq!(vec!["Die Straße"], search_case_insenstive("STRAẞE", contents));
+        assert_eq!(vec!["20 K"], search_case_insenstive("20 k", contents));
+    }
+
+    #[test]
+    fn unknown_flag() {
+        let args = ["minigrepr", "--frobnicate", "frog", "."].map(String::from);
+        assert!(Config::build(args.into_iter()).is_err());
+    }
+
+    #[test]
+    fn regex_syntax_error() {
+        let config = build(&["-E", "(unclosed", "sample_text.txt"]);
+        assert!(run(config).is_err());
+    }
+
+    #[test]
+    fn replace_flags() {
+        let config = build(&["-E", "--replace", "$2-$1", "--in-place", r"(\w+)=(\w+)", "a.ini"]);
+        assert_eq!(Some(String::from("$2-$1")), config.replace);
+        assert!(config.in_place);
+
+        let args = ["minigrepr", "--in-place", "frog", "poem.txt"].map(String::from);
+        assert!(Config::build(args.into_iter()).is_err());
+        //-R means recursive in grep, so --replace has no short flag to mix it up with
+        let args = ["minigrepr", "-R", "toad", "frog", "poem.txt"].map(String::from);
+        assert!(Config::build(args.into_iter()).is_err());
+    }
+
+    #[test]
+    fn max_count_keeps_trailing_context() {
+        let config = build(&["-m", "2", "-A", "1", "frog"]);
+        let pattern = Pattern::build(&config).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, false);
+        let input: &[u8] = b"frog 1\ntoad\nfrog 2\nfrog 3\nfrog 4\n";
+        search_reader(&config, &pattern, STDIN_NAME, input, &mut printer).unwrap();
+        assert_eq!(
+            "frog 1\ntoad\nfrog 2\nfrog 3\n",
+            String::from_utf8(printer.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn multiline_matches_select_every_line_they_touch() {
+        let input: &[u8] = b"Exception in main\r\n  at foo\n  at bar\nException in worker\n  at baz\n";
+        let search = |args: &[&str]| {
+            let config = build(args);
+            let pattern = Pattern::build(&config).unwrap();
+            let mut printer = Printer::new(Vec::new(), &config, false);
+            search_reader(&config, &pattern, STDIN_NAME, input, &mut printer).unwrap();
+            String::from_utf8(printer.into_inner()).unwrap()
+        };
+
+        assert_eq!(
+            "1:Exception in main\n2:  at foo\n",
+            search(&["-U", "-n", "-E", r"^Exception.*\n\s+at foo$"])
+        );
+        assert_eq!(
+            "Exception in main\n  at foo\nException in worker\n  at baz\n",
+            search(&["-U", "-E", r"Exception in \w+\s+at"])
+        );
+        assert_eq!("in main\n  at\n", search(&["-U", "-o", "-m", "2", "-E", r"in main\s+at"]));
+        assert_eq!("  at bar\n", search(&["-U", "-v", "-E", r"Exception.*\n.*"]));
+        //without -U the pattern never sees a line ending
+        assert_eq!("", search(&["-E", r"main\s+at"]));
+    }
+
+    #[test]
+    fn context_flags() {
+        let config = build(&["-C", "2", "-A", "1", "frog", "poem.txt"]);
+        assert_eq!(1, config.after_context);
+        assert_eq!(2, config.before_context);
+
+        let args = ["minigrepr", "-A", "x", "frog", "poem.txt"].map(String::from);
+        assert!(Config::build(args.into_iter()).is_err());
+    }
+
+    #[test]
+    fn line_positions_match_str_lines() {
+        let contents = "one\r\ntwo\n\nthree\r";
+        let texts: Vec<&str> = lines(contents).map(|line| line.text).collect();
+        assert_eq!(contents.lines().collect::<Vec<_>>(), texts);
+        let offsets: Vec<usize> = lines(contents).map(|line| line.byte_offset).collect();
+        assert_eq!(vec![0, 5, 9, 10], offsets);
+    }
+
+    #[test]
+    fn default_files() {
+        assert_eq!(vec!["-"], build(&["frog"]).files);
+        assert_eq!(vec!["."], build(&["-r", "frog"]).files);
+    }
+
+    #[test]
+    fn invalid_utf8_is_searched_lossily() {
+        let config = build(&["-n", "-b", "frog"]);
+        let pattern = Pattern::build(&config).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, false);
+        let input: &[u8] = b"caf\xe9 au lait\r\nthe fr\xf6g\nthe frog\n";
+        search_reader(&config, &pattern, STDIN_NAME, input, &mut printer).unwrap();
+        assert_eq!("3:23:the frog\n", String::from_utf8(printer.into_inner()).unwrap());
+
+        let config = build(&["-n", "fr"]);
+        let pattern = Pattern::build(&config).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, false);
+        search_reader(&config, &pattern, STDIN_NAME, input, &mut printer).unwrap();
+        assert_eq!(
+            "2:the fr\u{FFFD}g\n3:the frog\n",
+            String::from_utf8(printer.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn walk_honors_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src/generated")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join("src/.ignore"), "generated/\n").unwrap();
+        fs::write(root.join("notes.txt"), "frog").unwrap();
+        fs::write(root.join("debug.log"), "frog").unwrap();
+        fs::write(root.join("src/main.rs"), "frog").unwrap();
+        fs::write(root.join("src/generated/out.rs"), "frog").unwrap();
+
+        let errors = Cell::new(0);
+        let config = build(&["-r", "frog"]);
+        let found: Vec<PathBuf> = walk(root.to_str().unwrap(), &config, &errors)
+            .unwrap()
+            .collect();
+        assert_eq!(0, errors.get());
+        assert_eq!(vec![root.join("notes.txt"), root.join("src/main.rs")], found);
+    }
+
+    #[test]
+    fn walk_filters_types_and_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("vendor")).unwrap();
+        for file in ["README.md", "main.rs", "app.js", "app.min.js", "vendor/lib.js"] {
+            fs::write(root.join(file), "frog").unwrap();
+        }
+        let errors = Cell::new(0);
+        let walk_with = |args: &[&str]| -> Vec<PathBuf> {
+            let mut args = args.to_vec();
+            args.push("frog");
+            walk(root.to_str().unwrap(), &build(&args), &errors)
+                .unwrap()
+                .map(|path| path.strip_prefix(root).unwrap().to_path_buf())
+                .collect()
+        };
+
+        assert_eq!(vec![PathBuf::from("main.rs")], walk_with(&["-t", "rust"]));
+        assert_eq!(
+            vec![PathBuf::from("README.md"), PathBuf::from("main.rs")],
+            walk_with(&["-T", "js"])
+        );
+        assert_eq!(
+            vec![PathBuf::from("app.js")],
+            walk_with(&["-t", "js", "-g", "!*.min.js", "-g", "!vendor/"])
+        );
+        assert_eq!(vec![PathBuf::from("vendor/lib.js")], walk_with(&["-g", "vendor/*"]));
+        assert_eq!(0, errors.get());
+
+        let config = build(&["-t", "frogscript", "frog"]);
+        assert!(walk(root.to_str().unwrap(), &config, &errors).is_err());
+    }
+
+    #[test]
+    fn binary_detection() {
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\x00"));
+        assert!(!is_binary("safe, fast, productive.".as_bytes()));
+    }
+}
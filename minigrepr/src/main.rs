@@ -8,16 +8,20 @@ fn main() {
     //dbg!(&args);
 
     //use unwrap_or_else idiom when a function returns a Result with Ok(v) on success or Err(e) on error
+    //help and version go to stdout with status 0, usage errors exit with 2 like grep
     let config = Config::build(args).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {err}");
-        process::exit(1);
+        if !err.use_stderr() {
+            err.exit();
+        }
+        eprintln!("{}", err.message);
+        process::exit(2);
     });
 
-    println!("Searching for \"{}\" \nIn file {}", config.query, config.file_path);
-
-    //use if let idiom if a function returns only Err(e) on error and () on success
-    if let Err(e) = minigrepr::run(config) {
-        eprintln!("Application Error: {e}");
-        process::exit(1);
+    match minigrepr::run(config) {
+        Ok(status) => process::exit(status.code()),
+        Err(e) => {
+            eprintln!("Application Error: {e}");
+            process::exit(2);
+        }
     }
-}
\ No newline at end of file
+}
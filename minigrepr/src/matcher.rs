@@ -0,0 +1,346 @@
+//! Matching the patterns against a single line
+
+use std::error::Error;
+use std::ops::Range;
+
+use aho_corasick::{AhoCorasick, Input, MatchKind};
+use regex::{Regex, RegexBuilder};
+
+use crate::fuzzy::Fuzzy;
+use crate::{Config, Matcher};
+
+/// The patterns as they will be matched against each line
+pub(crate) struct Pattern {
+    kind: Kind,
+    /// Only accept matches that start and end at word boundaries (`-w`)
+    word: bool,
+    /// Each pattern compiled on its own, to expand `$1` style capture references
+    /// in replacements against in regex mode. Joined into one alternation the
+    /// patterns' groups are numbered one after another, so `$1` would refer to
+    /// the first pattern's group whichever pattern matched.
+    captures: Vec<Regex>,
+}
+
+enum Kind {
+    Literal(String),
+    /// Several case sensitive literals, found in a single pass over the line
+    Literals(AhoCorasick),
+    /// Regular expressions, literals matched case insensitively and whole line
+    /// matches, with several patterns joined into one alternation
+    Regex(Regex),
+    /// Substrings within an edit distance of any of the patterns (`--fuzzy`)
+    Fuzzy(Fuzzy),
+}
+
+impl Pattern {
+    pub(crate) fn build(config: &Config) -> Result<Pattern, Box<dyn Error>> {
+        let patterns = &config.patterns;
+        //a whole line match is anchored at both ends, which takes a regular expression
+        let plain = !config.regex && !config.ignore_case && !config.line_regexp;
+
+        let kind = if let Some(max_distance) = config.fuzzy {
+            Kind::Fuzzy(Fuzzy::new(patterns, max_distance, config.ignore_case)?)
+        } else if patterns.is_empty() || plain {
+            match patterns.as_slice() {
+                [query] => Kind::Literal(query.clone()),
+                //an automaton with no patterns never matches, just like an empty -f file in grep
+                _ => Kind::Literals(
+                    AhoCorasick::builder()
+                        .match_kind(MatchKind::LeftmostLongest)
+                        .build(patterns)?,
+                ),
+            }
+        } else {
+            Kind::Regex(compile(patterns, config)?)
+        };
+
+        let captures = match (&kind, config.regex) {
+            (Kind::Regex(re), true) if patterns.len() == 1 => vec![re.clone()],
+            (Kind::Regex(_), true) => patterns
+                .iter()
+                .map(|pattern| compile(std::slice::from_ref(pattern), config))
+                .collect::<Result<_, _>>()?,
+            _ => Vec::new(),
+        };
+
+        Ok(Pattern {
+            kind,
+            //-x already pins the match to the whole line
+            word: config.word_regexp && !config.line_regexp,
+            captures,
+        })
+    }
+
+    /// How many edits away from the patterns a match is, in fuzzy mode
+    pub(crate) fn distance(&self, matched: &str) -> Option<usize> {
+        match &self.kind {
+            Kind::Fuzzy(fuzzy) => Some(fuzzy.distance(matched)),
+            _ => None,
+        }
+    }
+
+    /// Line with every match replaced by template, along with the byte ranges
+    /// the replacements ended up at. In regex mode `$1`, `${name}` and `$0`
+    /// in template refer to the match's capture groups; otherwise template is
+    /// inserted as is.
+    pub(crate) fn replace_all(&self, line: &str, template: &str) -> (String, Vec<Range<usize>>) {
+        let mut replaced = String::with_capacity(line.len());
+        let mut spans = Vec::new();
+        let mut copied = 0;
+
+        for found in self.find_iter(line) {
+            replaced.push_str(&line[copied..found.start]);
+            let start = replaced.len();
+            match &self.kind {
+                Kind::Regex(_) if !self.captures.is_empty() => {
+                    //the alternation takes the first pattern that matches where the match starts,
+                    //and that pattern's leftmost match from there is the match itself
+                    let captures = self
+                        .captures
+                        .iter()
+                        .filter_map(|re| re.captures_at(line, found.start))
+                        .find(|captures| captures.get_match().start() == found.start)
+                        .unwrap();
+                    captures.expand(template, &mut replaced);
+                }
+                _ => replaced.push_str(template),
+            }
+            spans.push(start..replaced.len());
+            copied = found.end;
+        }
+        replaced.push_str(&line[copied..]);
+
+        (replaced, spans)
+    }
+}
+
+impl Matcher for Pattern {
+    /// The leftmost match, skipping matches that aren't whole words with `-w`
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        let mut at = start;
+
+        loop {
+            let found = self.kind.find_at(line, at)?;
+            if !self.word || at_word_boundaries(line, &found) {
+                return Some(found);
+            }
+            //like grep, try again just past the start of the rejected match
+            at = found.start + char_len_at(line, found.start).max(1);
+            if at > line.len() {
+                return None;
+            }
+        }
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        if self.word {
+            return self.find_at(line, 0).is_some();
+        }
+
+        match &self.kind {
+            Kind::Literal(query) => line.contains(query.as_str()),
+            Kind::Literals(literals) => literals.is_match(line),
+            Kind::Regex(re) => re.is_match(line),
+            Kind::Fuzzy(fuzzy) => fuzzy.find_at(line, 0).is_some(),
+        }
+    }
+}
+
+impl Kind {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        match self {
+            Kind::Literal(query) => line[start..]
+                .find(query.as_str())
+                .map(|offset| start + offset..start + offset + query.len()),
+            Kind::Literals(literals) => literals
+                .find(Input::new(line).range(start..))
+                .map(|found| found.range()),
+            Kind::Regex(re) => re.find_at(line, start).map(|found| found.range()),
+            Kind::Fuzzy(fuzzy) => fuzzy.find_at(line, start).map(|(found, _)| found),
+        }
+    }
+}
+
+/// Join patterns into one regular expression that matches any of them, as
+/// config says to match them
+fn compile(patterns: &[String], config: &Config) -> Result<Regex, regex::Error> {
+    let alternation: Vec<String> = patterns
+        .iter()
+        .map(|pattern| match config.regex {
+            true => format!("(?:{pattern})"),
+            false => regex::escape(pattern),
+        })
+        .collect();
+    let mut alternation = alternation.join("|");
+    if config.line_regexp {
+        alternation = format!("^(?:{alternation})$");
+    }
+
+    //compile the patterns once up front so syntax errors surface as an Err instead of a panic
+    //with -U the pattern runs over whole files, where ^ and $ still mean a line's ends
+    RegexBuilder::new(&alternation)
+        .case_insensitive(config.ignore_case)
+        .multi_line(config.multiline)
+        .build()
+}
+
+/// Whether span is neither preceded nor followed by a word character, so it
+/// starts and ends on a word boundary of line
+pub(crate) fn at_word_boundaries(line: &str, span: &Range<usize>) -> bool {
+    let before = line[..span.start].chars().next_back();
+    let after = line[span.end..].chars().next();
+    !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+}
+
+/// Letters, digits and underscore make up words, as in grep
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+pub(crate) fn char_len_at(line: &str, offset: usize) -> usize {
+    line[offset..].chars().next().map_or(0, char::len_utf8)
+}
+
+/// Match query literally, comparing characters by their Unicode simple case
+/// folding. Unlike lowercasing both sides this needs no allocation per line, and
+/// match offsets stay valid in the original line even where a character's
+/// lowercase form has a different length, like the Turkish `İ`.
+pub(crate) fn case_insensitive_literal(query: &str) -> Regex {
+    RegexBuilder::new(&regex::escape(query))
+        .case_insensitive(true)
+        .build()
+        .expect("an escaped literal is always a valid regular expression")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(args: &[&str]) -> Pattern {
+        let args = std::iter::once("minigrepr").chain(args.iter().copied());
+        Pattern::build(&Config::build(args.map(String::from)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn literal_spans() {
+        assert_eq!(
+            vec![2..4, 7..9],
+            pattern(&["ab", "-"]).find_iter("xxabxxxab")
+        );
+    }
+
+    #[test]
+    fn regex_spans() {
+        assert_eq!(
+            vec![0..3, 4..7],
+            pattern(&["-E", "[a-z]og", "-"]).find_iter("dog log")
+        );
+    }
+
+    #[test]
+    fn any_of_several_literals() {
+        let pattern = pattern(&["-e", "ERR", "-e", "ERROR", "-e", "WARN", "-"]);
+        assert!(matches!(pattern.kind, Kind::Literals(_)));
+        assert_eq!(vec![0..5, 9..13], pattern.find_iter("ERROR at WARN level"));
+        assert!(!pattern.is_match("all good"));
+    }
+
+    #[test]
+    fn any_of_several_regexes() {
+        let pattern = pattern(&["-E", "-i", "-e", "fr.g", "-e", "t(o|oa)d", "-"]);
+        assert_eq!(vec![4..8, 13..17], pattern.find_iter("The FROG and toad"));
+    }
+
+    #[test]
+    fn no_patterns_match_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let empty = dir.path().join("empty.txt");
+        std::fs::write(&empty, "").unwrap();
+        for flags in [&[][..], &["-E"][..], &["-i"][..]] {
+            let mut args = flags.to_vec();
+            args.extend(["-f", empty.to_str().unwrap(), "-"]);
+            assert!(!pattern(&args).is_match("anything"));
+        }
+    }
+
+    #[test]
+    fn case_folding() {
+        //capital sharp s folds to ß, but ß does not expand to "ss" under simple folding
+        assert!(pattern(&["-i", "straẞe", "-"]).is_match("Straße"));
+        assert!(!pattern(&["-i", "strasse", "-"]).is_match("Straße"));
+        assert!(pattern(&["-i", "ΣΊΣΥΦΟΣ", "-"]).is_match("σίσυφος"));
+    }
+
+    #[test]
+    fn case_insensitive_spans_point_into_original_line() {
+        //'İ' is 2 bytes but lowercases to the 3 byte "i\u{307}"
+        let line = "İ ERROR and Error";
+        assert_eq!(
+            vec![3..8, 13..18],
+            pattern(&["-i", "error", "-"]).find_iter(line)
+        );
+        assert_eq!(&line[3..8], "ERROR");
+    }
+
+    #[test]
+    fn word_matches() {
+        let line = "stderr: err_code err, (err)";
+        assert_eq!(
+            vec![17..20, 23..26],
+            pattern(&["-w", "err", "-"]).find_iter(line)
+        );
+        assert_eq!(
+            vec![17..20, 23..26],
+            pattern(&["-w", "-i", "ERR", "-"]).find_iter(line)
+        );
+        assert_eq!(
+            vec![17..20, 23..26],
+            pattern(&["-w", "-e", "err", "-e", "std", "-"]).find_iter(line)
+        );
+        assert!(!pattern(&["-w", "err", "-"]).is_match("stderr errno"));
+        assert!(pattern(&["-w", "-E", r"e\w+", "-"]).is_match("stderr errno"));
+    }
+
+    #[test]
+    fn whole_line_matches() {
+        assert!(pattern(&["-x", "err", "-"]).is_match("err"));
+        assert!(!pattern(&["-x", "err", "-"]).is_match("err "));
+        assert!(pattern(&["-x", "-i", "-e", "ok", "-e", "Err", "-"]).is_match("ERR"));
+        assert!(pattern(&["-x", "-E", "e.*r", "-"]).is_match("error"));
+        assert!(!pattern(&["-x", "-E", "e|error", "-"]).is_match("errors"));
+        assert!(pattern(&["-x", "-w", "a.b", "-"]).is_match("a.b"));
+    }
+
+    #[test]
+    fn replacements() {
+        let line = "key=value other=thing";
+        assert_eq!(
+            (String::from("value:key thing:other"), vec![0..9, 10..21]),
+            pattern(&["-E", r"(\w+)=(\w+)", "-"]).replace_all(line, "$2:$1")
+        );
+        assert_eq!(
+            String::from("KEY=value other=thing"),
+            pattern(&["-E", "-w", "k[a-z]+", "-"]).replace_all(line, "KEY").0
+        );
+        //each pattern numbers its own groups
+        assert_eq!(
+            String::from("value key thing other"),
+            pattern(&["-E", "-e", r"(\w+)=(\w+)", "-e", r"(\w+):(\w+)", "-"])
+                .replace_all("key=value other:thing", "$2 $1")
+                .0
+        );
+        //outside regex mode the template is taken literally
+        assert_eq!(
+            String::from("$1=value other=thing"),
+            pattern(&["-i", "KEY", "-"]).replace_all(line, "$1").0
+        );
+    }
+
+    #[test]
+    fn fuzzy_matches() {
+        let fuzzy = pattern(&["--fuzzy", "1", "-w", "frog", "-"]);
+        assert_eq!(vec![9..13], fuzzy.find_iter("frogging fr0g"));
+        assert_eq!(Some(1), fuzzy.distance("fr0g"));
+        assert_eq!(None, pattern(&["frog", "-"]).distance("frog"));
+    }
+}
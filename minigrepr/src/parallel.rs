@@ -0,0 +1,177 @@
+//! Searching many files at once across a pool of worker threads
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use crate::printer::{Printer, Stats};
+use crate::{search_file, Config, Pattern};
+
+//how many files may be queued up for the workers ahead of the output
+const JOBS_PER_WORKER: usize = 4;
+
+/// Search files on `config.threads` worker threads, writing each file's results
+/// to `out` as one block and in the order the files were given. Returns the
+/// combined stats of every file.
+pub(crate) fn search_files(
+    config: &Config,
+    pattern: &Pattern,
+    files: impl Iterator<Item = String>,
+    with_filename: bool,
+    out: impl Write,
+) -> io::Result<Stats> {
+    let (job_sender, job_receiver) =
+        mpsc::sync_channel::<(usize, String)>(config.threads * JOBS_PER_WORKER);
+    let (result_sender, result_receiver) = mpsc::channel();
+
+    //share the receiving end between workers, like a thread pool would
+    let job_receiver = Mutex::new(job_receiver);
+
+    thread::scope(|scope| {
+        for _ in 0..config.threads {
+            let job_receiver = &job_receiver;
+            let result_sender = result_sender.clone();
+
+            scope.spawn(move || loop {
+                let job = job_receiver.lock().unwrap().recv();
+                let Ok((index, file)) = job else {
+                    break;
+                };
+
+                let mut printer = Printer::new(Vec::new(), config, with_filename);
+                let result = search_file(config, pattern, &file, &mut printer);
+                let result = result.map(|()| (printer.stats(), printer.into_inner()));
+                if result_sender.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_sender);
+
+        let mut output = OrderedOutput::new(out, config);
+        let mut stats = Stats::default();
+        for job in files.enumerate() {
+            //a closed channel means every worker has gone, which only happens on a panic
+            if job_sender.send(job).is_err() {
+                break;
+            }
+            while let Ok((index, result)) = result_receiver.try_recv() {
+                let (file_stats, block) = result?;
+                stats.add(file_stats);
+                output.push(index, block)?;
+            }
+        }
+        drop(job_sender);
+
+        for (index, result) in result_receiver {
+            let (file_stats, block) = result?;
+            stats.add(file_stats);
+            output.push(index, block)?;
+        }
+        Ok(stats)
+    })
+}
+
+/// Writes per-file output blocks in file order as they become available
+struct OrderedOutput<W: Write> {
+    out: W,
+    next: usize,
+    pending: BTreeMap<usize, Vec<u8>>,
+    //blocks from different files get a group separator between them when context is printed
+    separate: bool,
+    written_any: bool,
+}
+
+impl<W: Write> OrderedOutput<W> {
+    fn new(out: W, config: &Config) -> OrderedOutput<W> {
+        OrderedOutput {
+            out,
+            next: 0,
+            pending: BTreeMap::new(),
+            separate: crate::printer::separates_groups(config),
+            written_any: false,
+        }
+    }
+
+    fn push(&mut self, index: usize, block: Vec<u8>) -> io::Result<()> {
+        self.pending.insert(index, block);
+
+        while let Some(block) = self.pending.remove(&self.next) {
+            self.next += 1;
+            if block.is_empty() {
+                continue;
+            }
+            if self.separate && self.written_any {
+                writeln!(self.out, "{}", crate::printer::GROUP_SEPARATOR)?;
+            }
+            self.written_any = true;
+            self.out.write_all(&block)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn search(args: &[&str], files: &[String]) -> String {
+        let args = std::iter::once("minigrepr").chain(args.iter().copied());
+        let config = Config::build(args.map(String::from)).unwrap();
+        let pattern = Pattern::build(&config).unwrap();
+        let mut out = Vec::new();
+        search_files(&config, &pattern, files.iter().cloned(), true, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn output_is_grouped_in_file_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<String> = (0..50)
+            .map(|i| {
+                let path = dir.path().join(format!("{i:02}.log"));
+                let contents = "ok\nerror\n".repeat(i % 7) + "ok\n";
+                fs::write(&path, contents).unwrap();
+                path.display().to_string()
+            })
+            .collect();
+
+        let expected: String = files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| format!("{file}:error\n").repeat(i % 7))
+            .collect();
+        assert_eq!(expected, search(&["-j", "8", "error", "."], &files));
+    }
+
+    #[test]
+    fn separator_between_files_with_context() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<String> = ["a", "b", "c"]
+            .iter()
+            .map(|name| {
+                let path = dir.path().join(name);
+                fs::write(&path, if *name == "b" { "quiet\n" } else { "error\n" }).unwrap();
+                path.display().to_string()
+            })
+            .collect();
+
+        assert_eq!(
+            format!("{}:error\n--\n{}:error\n", files[0], files[2]),
+            search(&["-j", "3", "-C", "1", "error", "."], &files)
+        );
+        //modes that print no context print no separator either, just as a serial search
+        let (a, c) = (&files[0], &files[2]);
+        for (args, expected) in [
+            (["-L", "quiet"], format!("{a}\n{c}\n")),
+            (["-l", "error"], format!("{a}\n{c}\n")),
+            (["-o", "error"], format!("{a}:error\n{c}:error\n")),
+        ] {
+            let args = [&["-j", "3", "-C", "1"], &args[..], &["."]].concat();
+            assert_eq!(expected, search(&args, &files));
+        }
+    }
+}
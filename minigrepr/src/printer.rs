@@ -0,0 +1,551 @@
+//! Formatting of search results, grep style
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::slice;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::{Config, Line};
+
+//separates non-adjacent groups of lines when context is requested
+pub(crate) const GROUP_SEPARATOR: &str = "--";
+
+//ANSI escapes, using the same colors as GNU grep
+const COLOR_MATCH: &str = "\x1b[1;31m";
+const COLOR_PATH: &str = "\x1b[35m";
+const COLOR_NUMBER: &str = "\x1b[32m";
+const COLOR_DISTANCE: &str = "\x1b[33m";
+const COLOR_SEPARATOR: &str = "\x1b[36m";
+const COLOR_RESET: &str = "\x1b[0m";
+
+/// A line held back in case it turns out to be before-context of a later match
+struct HeldLine {
+    number: usize,
+    byte_offset: usize,
+    text: String,
+}
+
+/// Running totals of what was searched and found
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Stats {
+    pub(crate) searches: usize,
+    pub(crate) searches_with_match: usize,
+    pub(crate) matched_lines: usize,
+    pub(crate) matches: usize,
+    pub(crate) bytes_searched: usize,
+    pub(crate) errors: usize,
+}
+
+impl Stats {
+    pub(crate) fn add(&mut self, other: Stats) {
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+        self.bytes_searched += other.bytes_searched;
+        self.errors += other.errors;
+    }
+}
+
+/// Whether groups of lines, and the output for different files, are separated
+/// by `--`: only when lines are printed with context around them, which `-o`
+/// turns off and `-q`, `-c`, `-l`, `-L` and `--json` print no lines for
+pub(crate) fn separates_groups(config: &Config) -> bool {
+    let prints_context = !config.only_matching
+        && !config.quiet
+        && !config.count
+        && !config.files_with_matches
+        && !config.files_without_match
+        && !config.json;
+    prints_context && (config.before_context > 0 || config.after_context > 0)
+}
+
+/// Writes selected lines, their context and per-file summaries to `out`
+pub(crate) struct Printer<W: Write> {
+    out: W,
+    with_filename: bool,
+    line_number: bool,
+    byte_offset: bool,
+    count: bool,
+    files_with_matches: bool,
+    files_without_match: bool,
+    only_matching: bool,
+    quiet: bool,
+    color: bool,
+    json: bool,
+    before_context: usize,
+    after_context: usize,
+    separate_groups: bool,
+    //state for the file being printed
+    path: String,
+    last_printed: Option<usize>,
+    after_remaining: usize,
+    before: VecDeque<HeldLine>,
+    //whether the JSON begin message for this file has been written
+    begun: bool,
+    file_stats: Stats,
+    stats: Stats,
+    //whether any group of lines has been printed, across all files
+    printed_any: bool,
+}
+
+impl<W: Write> Printer<W> {
+    pub(crate) fn new(out: W, config: &Config, with_filename: bool) -> Printer<W> {
+        Printer {
+            out,
+            with_filename,
+            line_number: config.line_number,
+            byte_offset: config.byte_offset,
+            count: config.count,
+            files_with_matches: config.files_with_matches,
+            files_without_match: config.files_without_match,
+            only_matching: config.only_matching,
+            quiet: config.quiet,
+            color: config.color && !config.json,
+            json: config.json,
+            //context makes no sense around the bare matches -o prints
+            before_context: if config.only_matching {
+                0
+            } else {
+                config.before_context
+            },
+            after_context: if config.only_matching {
+                0
+            } else {
+                config.after_context
+            },
+            separate_groups: separates_groups(config),
+            path: String::new(),
+            last_printed: None,
+            after_remaining: 0,
+            before: VecDeque::with_capacity(config.before_context),
+            begun: false,
+            file_stats: Stats::default(),
+            stats: Stats::default(),
+            printed_any: false,
+        }
+    }
+
+    /// Start printing the results for the file called name
+    pub(crate) fn begin(&mut self, name: &str) {
+        self.path = name.to_string();
+        self.last_printed = None;
+        self.after_remaining = 0;
+        self.before.clear();
+        self.begun = false;
+        self.file_stats = Stats {
+            searches: 1,
+            ..Stats::default()
+        };
+    }
+
+    /// Handle the next line of the current file, whether or not it was selected.
+    /// `spans` are the matches to highlight when printing in color or to report as JSON,
+    /// and `distances` how far each one is from the pattern in fuzzy mode.
+    pub(crate) fn line(
+        &mut self,
+        line: Line,
+        selected: bool,
+        spans: &[Range<usize>],
+        distances: &[usize],
+    ) -> io::Result<()> {
+        if selected {
+            self.file_stats.matched_lines += 1;
+            self.file_stats.matches += spans.len();
+        }
+        if self.quiet || self.count || self.files_with_matches || self.files_without_match {
+            return Ok(());
+        }
+        if self.only_matching {
+            if selected {
+                for (i, span) in spans.iter().enumerate() {
+                    if span.is_empty() {
+                        continue;
+                    }
+                    let byte_offset = line.byte_offset + span.start;
+                    let matched = &line.text[span.clone()];
+                    let whole = 0..matched.len();
+                    let distance = distances.get(i).map(slice::from_ref).unwrap_or_default();
+                    self.write_line(
+                        line.number,
+                        byte_offset,
+                        matched,
+                        ':',
+                        slice::from_ref(&whole),
+                        distance,
+                    )?;
+                }
+            }
+            return Ok(());
+        }
+
+        if selected {
+            while let Some(held) = self.before.pop_front() {
+                self.write_line(held.number, held.byte_offset, &held.text, '-', &[], &[])?;
+            }
+            self.write_line(line.number, line.byte_offset, line.text, ':', spans, distances)?;
+            self.after_remaining = self.after_context;
+        } else if self.after_remaining > 0 {
+            self.write_line(line.number, line.byte_offset, line.text, '-', &[], &[])?;
+            self.after_remaining -= 1;
+        } else if self.before_context > 0 {
+            if self.before.len() == self.before_context {
+                self.before.pop_front();
+            }
+            self.before.push_back(HeldLine {
+                number: line.number,
+                byte_offset: line.byte_offset,
+                text: line.text.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Finish the current file, which had `selected` lines picked out of `bytes_searched` bytes
+    pub(crate) fn end(&mut self, selected: usize, bytes_searched: usize) -> io::Result<()> {
+        self.file_stats.bytes_searched = bytes_searched;
+        if selected > 0 {
+            self.file_stats.searches_with_match = 1;
+        }
+        self.stats.add(self.file_stats);
+        if self.quiet {
+            return Ok(());
+        }
+
+        if self.json {
+            if self.begun {
+                let end = json!({
+                    "type": "end",
+                    "data": {
+                        "path": { "text": self.path },
+                        "stats": stats_json(self.file_stats),
+                    },
+                });
+                writeln!(self.out, "{end}")?;
+            }
+        } else if self.files_with_matches || self.files_without_match {
+            if (selected > 0) == self.files_with_matches {
+                self.write_path()?;
+                writeln!(self.out)?;
+            }
+        } else if self.count {
+            if self.with_filename {
+                self.write_path()?;
+                self.write_separator(':')?;
+            }
+            writeln!(self.out, "{selected}")?;
+        }
+
+        Ok(())
+    }
+
+    /// Report a file that couldn't be searched
+    pub(crate) fn error(&mut self, name: &str, err: impl std::fmt::Display) {
+        eprintln!("{name}: {err}");
+        self.stats.errors += 1;
+    }
+
+    /// Whether lines of after-context are still owed to the last selected line
+    pub(crate) fn after_context_pending(&self) -> bool {
+        self.after_remaining > 0
+    }
+
+    /// Totals for every file printed so far
+    pub(crate) fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Give back the underlying writer
+    pub(crate) fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Write one output line; `separator` is ':' for selected lines and '-' for context
+    fn write_line(
+        &mut self,
+        number: usize,
+        byte_offset: usize,
+        text: &str,
+        separator: char,
+        spans: &[Range<usize>],
+        distances: &[usize],
+    ) -> io::Result<()> {
+        if self.json {
+            return self.write_json_line(number, byte_offset, text, separator, spans, distances);
+        }
+
+        let adjacent = self.last_printed.is_some_and(|last| last + 1 == number);
+        if self.separate_groups && self.printed_any && !adjacent {
+            self.paint(COLOR_SEPARATOR, GROUP_SEPARATOR)?;
+            writeln!(self.out)?;
+        }
+        self.printed_any = true;
+        self.last_printed = Some(number);
+
+        if self.with_filename {
+            self.write_path()?;
+            self.write_separator(separator)?;
+        }
+        if self.line_number {
+            self.paint(COLOR_NUMBER, number)?;
+            self.write_separator(separator)?;
+        }
+        if self.byte_offset {
+            self.paint(COLOR_NUMBER, byte_offset)?;
+            self.write_separator(separator)?;
+        }
+        //a fuzzy line is as close as its closest match, labelled so it can't pass for a line number
+        if let Some(distance) = distances.iter().min() {
+            write!(self.out, "distance=")?;
+            self.paint(COLOR_DISTANCE, distance)?;
+            self.write_separator(separator)?;
+        }
+
+        let mut written = 0;
+        for span in spans.iter().filter(|span| !span.is_empty()) {
+            write!(self.out, "{}", &text[written..span.start])?;
+            self.paint(COLOR_MATCH, &text[span.clone()])?;
+            written = span.end;
+        }
+        writeln!(self.out, "{}", &text[written..])
+    }
+
+    /// Write a line as a JSON `match` or `context` message, preceded by the
+    /// file's `begin` message if this is its first line
+    fn write_json_line(
+        &mut self,
+        number: usize,
+        byte_offset: usize,
+        text: &str,
+        separator: char,
+        spans: &[Range<usize>],
+        distances: &[usize],
+    ) -> io::Result<()> {
+        if !self.begun {
+            self.begun = true;
+            let begin = json!({ "type": "begin", "data": { "path": { "text": self.path } } });
+            writeln!(self.out, "{begin}")?;
+        }
+
+        let submatches: Vec<_> = spans
+            .iter()
+            .enumerate()
+            .map(|(i, span)| {
+                let mut submatch = json!({
+                    "match": { "text": &text[span.clone()] },
+                    "start": span.start,
+                    "end": span.end,
+                });
+                if let Some(distance) = distances.get(i) {
+                    submatch["distance"] = json!(distance);
+                }
+                submatch
+            })
+            .collect();
+        let message = json!({
+            "type": if separator == ':' { "match" } else { "context" },
+            "data": {
+                "path": { "text": self.path },
+                "lines": { "text": text },
+                "line_number": number,
+                "absolute_offset": byte_offset,
+                "submatches": submatches,
+            },
+        });
+        writeln!(self.out, "{message}")
+    }
+
+    fn write_path(&mut self) -> io::Result<()> {
+        let path = std::mem::take(&mut self.path);
+        let result = self.paint(COLOR_PATH, &path);
+        self.path = path;
+        result
+    }
+
+    fn write_separator(&mut self, separator: char) -> io::Result<()> {
+        self.paint(COLOR_SEPARATOR, separator)
+    }
+
+    /// Write value, wrapped in the given color when color output is on
+    fn paint(&mut self, color: &str, value: impl std::fmt::Display) -> io::Result<()> {
+        if self.color {
+            write!(self.out, "{color}{value}{COLOR_RESET}")
+        } else {
+            write!(self.out, "{value}")
+        }
+    }
+}
+
+/// Write the JSON `summary` message that closes a search
+pub(crate) fn write_summary(
+    out: &mut impl Write,
+    stats: Stats,
+    elapsed: Duration,
+) -> io::Result<()> {
+    let summary = json!({
+        "type": "summary",
+        "data": {
+            "elapsed_total": {
+                "secs": elapsed.as_secs(),
+                "nanos": elapsed.subsec_nanos(),
+                "human": format!("{:.6}s", elapsed.as_secs_f64()),
+            },
+            "stats": stats_json(stats),
+        },
+    });
+    writeln!(out, "{summary}")
+}
+
+fn stats_json(stats: Stats) -> serde_json::Value {
+    json!({
+        "searches": stats.searches,
+        "searches_with_match": stats.searches_with_match,
+        "matched_lines": stats.matched_lines,
+        "matches": stats.matches,
+        "bytes_searched": stats.bytes_searched,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines;
+
+    fn print(args: &[&str], contents: &str, query: &str) -> String {
+        let mut args: Vec<&str> = args.to_vec();
+        args.extend(["--color", "never"]);
+        args.extend([query, "poem.txt"]);
+        let args = std::iter::once("minigrepr").chain(args).map(String::from);
+        let config = Config::build(args).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, false);
+        printer.begin("poem.txt");
+        let mut selected = 0;
+        for line in lines(contents) {
+            let spans: Vec<Range<usize>> = line
+                .text
+                .match_indices(query)
+                .map(|(start, matched)| start..start + matched.len())
+                .collect();
+            selected += !spans.is_empty() as usize;
+            printer.line(line, !spans.is_empty(), &spans, &[]).unwrap();
+        }
+        printer.end(selected, contents.len()).unwrap();
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    const CONTENTS: &str = "a\nb\nmatch\nc\nd\ne\nf\nmatch\ng\n";
+
+    #[test]
+    fn context_groups() {
+        assert_eq!(
+            "2-b\n3:match\n4-c\n--\n7-f\n8:match\n9-g\n",
+            print(&["-n", "-C", "1"], CONTENTS, "match")
+        );
+    }
+
+    #[test]
+    fn overlapping_context_is_not_repeated() {
+        assert_eq!(
+            "a\nb\nmatch\nc\nd\ne\nf\nmatch\ng\n",
+            print(&["-A", "3", "-B", "2"], CONTENTS, "match")
+        );
+    }
+
+    #[test]
+    fn only_matching() {
+        assert_eq!(
+            "1:0:ab\n1:3:ab\n3:11:ab\n",
+            print(&["-o", "-n", "-b", "-C", "1"], "ab ab\nxx\nx ab\n", "ab")
+        );
+    }
+
+    #[test]
+    fn files_without_match() {
+        assert_eq!("poem.txt\n", print(&["-L"], CONTENTS, "frog"));
+        assert_eq!("", print(&["-L"], CONTENTS, "match"));
+    }
+
+    #[test]
+    fn byte_offsets() {
+        assert_eq!(
+            "3:4:match\n8:18:match\n",
+            print(&["-n", "-b"], CONTENTS, "match")
+        );
+    }
+
+    #[test]
+    fn highlighted_matches() {
+        let args = ["minigrepr", "--color", "always", "-n", "o", "poem.txt"];
+        let config = Config::build(args.iter().map(|arg| arg.to_string())).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, true);
+        printer.begin("poem.txt");
+        let line = Line {
+            number: 7,
+            byte_offset: 0,
+            text: "a frog on",
+        };
+        printer.line(line, true, &[4..5, 7..8], &[]).unwrap();
+        assert_eq!(
+            "\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m7\x1b[0m\x1b[36m:\x1b[0ma fr\x1b[1;31mo\x1b[0mg \x1b[1;31mo\x1b[0mn\n",
+            String::from_utf8(printer.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn fuzzy_distances() {
+        let args = ["minigrepr", "--fuzzy", "1", "-n", "-o", "frog", "poem.txt"];
+        let config = Config::build(args.iter().map(|arg| arg.to_string())).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, false);
+        printer.begin("poem.txt");
+        let line = Line {
+            number: 3,
+            byte_offset: 0,
+            text: "a frg or a frog",
+        };
+        printer.line(line, true, &[2..5, 11..15], &[1, 0]).unwrap();
+        assert_eq!(
+            "3:distance=1:frg\n3:distance=0:frog\n",
+            String::from_utf8(printer.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn json_messages() {
+        let args = ["minigrepr", "--json", "-A", "1", "o", "poem.txt"];
+        let config = Config::build(args.iter().map(|arg| arg.to_string())).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, true);
+        printer.begin("poem.txt");
+        let mut lines = crate::lines("a frog on\nlog\n");
+        printer
+            .line(lines.next().unwrap(), true, &[4..5, 7..8], &[])
+            .unwrap();
+        printer.line(lines.next().unwrap(), false, &[], &[]).unwrap();
+        printer.end(1, 14).unwrap();
+
+        let messages: Vec<serde_json::Value> = String::from_utf8(printer.into_inner())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            vec!["begin", "match", "context", "end"],
+            messages
+                .iter()
+                .map(|m| m["type"].as_str().unwrap())
+                .collect::<Vec<_>>()
+        );
+        let data = &messages[1]["data"];
+        assert_eq!("poem.txt", data["path"]["text"]);
+        assert_eq!("a frog on", data["lines"]["text"]);
+        assert_eq!(1, data["line_number"]);
+        assert_eq!(7, data["submatches"][1]["start"]);
+        assert_eq!("o", data["submatches"][1]["match"]["text"]);
+        assert_eq!(2, messages[2]["data"]["line_number"]);
+        assert_eq!(10, messages[2]["data"]["absolute_offset"]);
+        assert_eq!(2, messages[3]["data"]["stats"]["matches"]);
+    }
+}
@@ -0,0 +1,514 @@
+//! The library interface: matchers that find patterns in a line, and a
+//! searcher that runs one over any reader
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::io::{self, BufRead};
+use std::ops::Range;
+
+use regex::Regex;
+
+use crate::fuzzy::Fuzzy;
+use crate::matcher::{case_insensitive_literal, char_len_at};
+use crate::trim_line_ending;
+
+/// Something that finds matches within a single line
+pub trait Matcher {
+    /// Byte range of the leftmost match in line starting at or after byte
+    /// offset `start`, if there is one
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>>;
+
+    /// Whether line has a match anywhere
+    fn is_match(&self, line: &str) -> bool {
+        self.find_at(line, 0).is_some()
+    }
+
+    /// Byte ranges of every non-overlapping match in line, left to right
+    fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        let mut spans = Vec::new();
+        let mut at = 0;
+
+        while let Some(found) = self.find_at(line, at) {
+            //step over empty matches so the search always moves forward
+            at = match found.is_empty() {
+                true => found.end + char_len_at(line, found.end).max(1),
+                false => found.end,
+            };
+            spans.push(found);
+            if at > line.len() {
+                break;
+            }
+        }
+
+        spans
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for &M {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        (**self).find_at(line, start)
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        (**self).is_match(line)
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for Box<M> {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        (**self).find_at(line, start)
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        (**self).is_match(line)
+    }
+}
+
+/// Matches a string exactly, as `search` does
+#[derive(Debug, Clone)]
+pub struct LiteralMatcher {
+    query: String,
+}
+
+impl LiteralMatcher {
+    pub fn new(query: &str) -> LiteralMatcher {
+        LiteralMatcher {
+            query: query.to_string(),
+        }
+    }
+}
+
+impl Matcher for LiteralMatcher {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        line[start..]
+            .find(self.query.as_str())
+            .map(|offset| start + offset..start + offset + self.query.len())
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        line.contains(self.query.as_str())
+    }
+}
+
+/// Matches a string ignoring case, as `search_case_insenstive` does
+#[derive(Debug, Clone)]
+pub struct CaseInsensitiveMatcher {
+    re: Regex,
+}
+
+impl CaseInsensitiveMatcher {
+    pub fn new(query: &str) -> CaseInsensitiveMatcher {
+        CaseInsensitiveMatcher {
+            re: case_insensitive_literal(query),
+        }
+    }
+}
+
+impl Matcher for CaseInsensitiveMatcher {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        self.re.find_at(line, start).map(|found| found.range())
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        self.re.is_match(line)
+    }
+}
+
+/// Matches a regular expression
+#[derive(Debug, Clone)]
+pub struct RegexMatcher {
+    re: Regex,
+}
+
+impl RegexMatcher {
+    pub fn new(pattern: &str) -> Result<RegexMatcher, regex::Error> {
+        Regex::new(pattern).map(RegexMatcher::from)
+    }
+}
+
+impl From<Regex> for RegexMatcher {
+    fn from(re: Regex) -> RegexMatcher {
+        RegexMatcher { re }
+    }
+}
+
+impl Matcher for RegexMatcher {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        self.re.find_at(line, start).map(|found| found.range())
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        self.re.is_match(line)
+    }
+}
+
+/// Matches any substring within a Levenshtein edit distance of a string, as
+/// `--fuzzy` does
+#[derive(Debug, Clone)]
+pub struct FuzzyMatcher {
+    fuzzy: Fuzzy,
+}
+
+impl FuzzyMatcher {
+    /// Fails if max_distance edits could delete the whole query, since that
+    /// would match everywhere
+    pub fn new(
+        query: &str,
+        max_distance: usize,
+        ignore_case: bool,
+    ) -> Result<FuzzyMatcher, Box<dyn Error>> {
+        let fuzzy = Fuzzy::new(&[query.to_string()], max_distance, ignore_case)?;
+        Ok(FuzzyMatcher { fuzzy })
+    }
+
+    /// How many edits away from the query matched text is
+    pub fn distance(&self, matched: &str) -> usize {
+        self.fuzzy.distance(matched)
+    }
+}
+
+impl Matcher for FuzzyMatcher {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        self.fuzzy.find_at(line, start).map(|(found, _)| found)
+    }
+}
+
+/// A selected line: one with at least one match, or none when inverted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// Line number, starting at 1
+    pub line_number: usize,
+    /// Offset in bytes of the start of the line from the start of the input
+    pub byte_offset: usize,
+    /// The line without its line ending, with invalid UTF-8 replaced by `�`
+    pub line: String,
+    /// Byte ranges of the matches within `line`
+    pub spans: Vec<Range<usize>>,
+}
+
+impl Match {
+    /// The matched parts of the line
+    pub fn matched(&self) -> impl Iterator<Item = &str> {
+        self.spans.iter().map(|span| &self.line[span.clone()])
+    }
+}
+
+/// Runs a matcher over every line of a reader. This is the search `run` does
+/// for each file, so options like `invert_match` and `max_count` behave here
+/// as `-v` and `-m` do on the command line.
+///
+/// # Examples
+///
+/// ```
+/// use minigrepr::{CaseInsensitiveMatcher, Searcher};
+/// let contents = "\
+/// Rust:
+/// safe, fast, productive.
+/// Pick three.
+/// Trust me.";
+/// let searcher = Searcher::new(CaseInsensitiveMatcher::new("rust"));
+/// let found: Vec<_> = searcher
+///     .matches(contents.as_bytes())
+///     .map(|found| found.map(|found| (found.line_number, found.spans)))
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// assert_eq!(found, vec![(1, vec![0..4]), (4, vec![1..5])]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Searcher<M> {
+    matcher: M,
+    invert_match: bool,
+    max_count: Option<usize>,
+}
+
+impl<M: Matcher> Searcher<M> {
+    pub fn new(matcher: M) -> Searcher<M> {
+        Searcher {
+            matcher,
+            invert_match: false,
+            max_count: None,
+        }
+    }
+
+    pub fn matcher(&self) -> &M {
+        &self.matcher
+    }
+
+    /// Select the lines without a match instead, which come with no spans
+    pub fn invert_match(mut self, invert_match: bool) -> Searcher<M> {
+        self.invert_match = invert_match;
+        self
+    }
+
+    /// Stop after selecting max_count lines, if given
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use minigrepr::{LiteralMatcher, Searcher};
+    /// let searcher = Searcher::new(LiteralMatcher::new("frog"))
+    ///     .invert_match(true)
+    ///     .max_count(Some(1));
+    /// let found: Vec<_> = searcher
+    ///     .matches("frog\ntoad\nnewt\n".as_bytes())
+    ///     .map(|found| found.unwrap().line)
+    ///     .collect();
+    /// assert_eq!(found, vec!["toad"]);
+    /// ```
+    pub fn max_count(mut self, max_count: Option<usize>) -> Searcher<M> {
+        self.max_count = max_count;
+        self
+    }
+
+    /// Iterate over the lines of reader that are selected, reading one line at a time
+    pub fn matches<R: BufRead>(&self, reader: R) -> Matches<'_, M, R> {
+        Matches {
+            lines: self.lines(reader, true),
+        }
+    }
+
+    /// Call sink with each line of reader that is selected, stopping early
+    /// once it returns false
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use minigrepr::{RegexMatcher, Searcher};
+    /// let searcher = Searcher::new(RegexMatcher::new(r"\d+").unwrap());
+    /// let mut first = None;
+    /// searcher
+    ///     .search("no digits\nport 8080\nport 443\n".as_bytes(), |found| {
+    ///         first = Some(found.byte_offset + found.spans[0].start);
+    ///         false
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(first, Some(15));
+    /// ```
+    pub fn search<R: BufRead>(
+        &self,
+        reader: R,
+        mut sink: impl FnMut(Match) -> bool,
+    ) -> io::Result<()> {
+        for found in self.matches(reader) {
+            if !sink(found?) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every line of reader, selected or not, for printing context around the
+    /// selected ones. With spans false the spans are left empty, which saves
+    /// finding every match when only whether a line has one matters.
+    pub(crate) fn lines<R: BufRead>(&self, reader: R, spans: bool) -> Lines<'_, M, R> {
+        Lines {
+            searcher: self,
+            reader,
+            buf: Vec::new(),
+            spans,
+            line_number: 0,
+            byte_offset: 0,
+            selected: 0,
+        }
+    }
+}
+
+/// A line read by `Lines`
+pub(crate) struct SearchedLine<'a> {
+    pub(crate) number: usize,
+    pub(crate) byte_offset: usize,
+    /// The line without its line ending, with invalid UTF-8 replaced by `�`
+    pub(crate) text: Cow<'a, str>,
+    pub(crate) selected: bool,
+    /// Byte ranges of the matches in a selected line, unless inverted
+    pub(crate) spans: Vec<Range<usize>>,
+}
+
+/// Reads a searcher's lines one at a time, holding only the current line in memory
+pub(crate) struct Lines<'s, M, R> {
+    searcher: &'s Searcher<M>,
+    reader: R,
+    buf: Vec<u8>,
+    spans: bool,
+    line_number: usize,
+    byte_offset: usize,
+    selected: usize,
+}
+
+impl<M: Matcher, R: BufRead> Lines<'_, M, R> {
+    /// The next line, or None at the end of the reader. Lines past the
+    /// maximum count are never selected, but can still be read as context.
+    pub(crate) fn next_line(&mut self) -> io::Result<Option<SearchedLine<'_>>> {
+        let reached_max = self.reached_max();
+        self.buf.clear();
+        let len = self.reader.read_until(b'\n', &mut self.buf)?;
+        if len == 0 {
+            return Ok(None);
+        }
+        self.line_number += 1;
+        let byte_offset = self.byte_offset;
+        self.byte_offset += len;
+
+        let text = String::from_utf8_lossy(trim_line_ending(&self.buf));
+        let matcher = &self.searcher.matcher;
+        let (selected, spans) = if reached_max {
+            (false, Vec::new())
+        } else if self.searcher.invert_match {
+            (!matcher.is_match(&text), Vec::new())
+        } else if self.spans {
+            let spans = matcher.find_iter(&text);
+            (!spans.is_empty(), spans)
+        } else {
+            (matcher.is_match(&text), Vec::new())
+        };
+        if selected {
+            self.selected += 1;
+        }
+
+        Ok(Some(SearchedLine {
+            number: self.line_number,
+            byte_offset,
+            text,
+            selected,
+            spans,
+        }))
+    }
+
+    /// Whether as many lines as the maximum count have been selected
+    pub(crate) fn reached_max(&self) -> bool {
+        self.searcher
+            .max_count
+            .is_some_and(|max| self.selected >= max)
+    }
+
+    /// How many lines have been selected
+    pub(crate) fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// How many bytes have been read
+    pub(crate) fn bytes_read(&self) -> usize {
+        self.byte_offset
+    }
+}
+
+/// Iterator over the selected lines of a reader, made by `Searcher::matches`
+pub struct Matches<'s, M, R> {
+    lines: Lines<'s, M, R>,
+}
+
+impl<M: Matcher, R: BufRead> Iterator for Matches<'_, M, R> {
+    type Item = io::Result<Match>;
+
+    fn next(&mut self) -> Option<io::Result<Match>> {
+        loop {
+            if self.lines.reached_max() {
+                return None;
+            }
+            match self.lines.next_line() {
+                Ok(Some(line)) if line.selected => {
+                    return Some(Ok(Match {
+                        line_number: line.number,
+                        byte_offset: line.byte_offset,
+                        line: line.text.into_owned(),
+                        spans: line.spans,
+                    }))
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => return None,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENTS: &str = "The frog sat on a log.\r\nFROGS are green.\n";
+
+    fn search(matcher: impl Matcher, contents: &[u8]) -> Vec<Match> {
+        Searcher::new(matcher)
+            .matches(contents)
+            .collect::<io::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn each_matcher() {
+        let found = search(LiteralMatcher::new("og"), CONTENTS.as_bytes());
+        assert_eq!(1, found.len());
+        assert_eq!(vec![6..8, 19..21], found[0].spans);
+
+        let found = search(CaseInsensitiveMatcher::new("frog"), CONTENTS.as_bytes());
+        assert_eq!(
+            vec![1, 2],
+            found
+                .iter()
+                .map(|found| found.line_number)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            (24, vec!["FROG"]),
+            (found[1].byte_offset, found[1].matched().collect())
+        );
+
+        let found = search(RegexMatcher::new(r"\bl\w+").unwrap(), CONTENTS.as_bytes());
+        assert_eq!(vec!["log"], found[0].matched().collect::<Vec<_>>());
+
+        let fuzzy = FuzzyMatcher::new("frog", 1, false).unwrap();
+        assert_eq!(1, fuzzy.distance("frg"));
+        let found = search(&fuzzy, b"\xff frg\n");
+        assert_eq!("\u{fffd} frg", found[0].line);
+        assert_eq!(vec!["frg"], found[0].matched().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn boxed_matchers() {
+        let matchers: Vec<Box<dyn Matcher>> = vec![
+            Box::new(LiteralMatcher::new("green")),
+            Box::new(RegexMatcher::new("gre+n").unwrap()),
+        ];
+        for matcher in matchers {
+            assert_eq!(2, search(matcher, CONTENTS.as_bytes())[0].line_number);
+        }
+    }
+
+    #[test]
+    fn invert_and_max_count() {
+        let searcher = Searcher::new(LiteralMatcher::new("frog")).invert_match(true);
+        let found: Vec<Match> = searcher
+            .matches(&b"toad\nfrog\nnewt\n"[..])
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            vec![(1, Vec::new()), (3, Vec::new())],
+            found
+                .into_iter()
+                .map(|found| (found.line_number, found.spans))
+                .collect::<Vec<_>>()
+        );
+
+        let searcher = Searcher::new(LiteralMatcher::new("r")).max_count(Some(1));
+        let mut lines = searcher.lines(CONTENTS.as_bytes(), false);
+        assert!(lines.next_line().unwrap().unwrap().selected);
+        //past the maximum lines can still be read as context
+        let line = lines.next_line().unwrap().unwrap();
+        assert_eq!((2, false), (line.number, line.selected));
+        assert!(lines.next_line().unwrap().is_none());
+        assert_eq!((1, CONTENTS.len()), (lines.selected(), lines.bytes_read()));
+    }
+
+    #[test]
+    fn sink_can_stop_early() {
+        let mut seen = Vec::new();
+        Searcher::new(LiteralMatcher::new("o"))
+            .search(CONTENTS.as_bytes(), |found| {
+                seen.push(found.line_number);
+                false
+            })
+            .unwrap();
+        assert_eq!(vec![1], seen);
+    }
+}
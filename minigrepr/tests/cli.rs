@@ -0,0 +1,201 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::error::Error;
+
+type TestResult = Result<(), Box<dyn Error>>;
+
+const PRG: &str = "minigrepr";
+const POEM: &str = "tests/inputs/poem.txt";
+const FROGS: &str = "tests/inputs/frogs.txt";
+const FROGS_GZ: &str = "tests/inputs/frogs.txt.gz";
+
+// --------------------------------------------------
+#[test]
+fn usage() -> TestResult {
+    for flag in &["-h", "--help"] {
+        Command::cargo_bin(PRG)?
+            .arg(flag)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("USAGE"));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_no_args() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("USAGE"));
+    Ok(())
+}
+
+// --------------------------------------------------
+fn run(args: &[&str], expected: &str) -> TestResult {
+    Command::cargo_bin(PRG)?
+        .env_remove("IGNORE_CASE")
+        .args(args)
+        .assert()
+        .success()
+        .stdout(expected.to_string());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn only_matches_on_stdout() -> TestResult {
+    run(&["frog", POEM], "How public, like a frog\n")
+}
+
+#[test]
+fn line_numbers() -> TestResult {
+    run(&["-n", "nobody", POEM], "1:I'm nobody! Who are you?\n2:Are you nobody, too?\n")
+}
+
+#[test]
+fn invert_match() -> TestResult {
+    run(&["-v", "-i", "frog", FROGS], "Nothing to see here.\n")
+}
+
+#[test]
+fn count_multiple_files() -> TestResult {
+    run(
+        &["-c", "frog", POEM, FROGS],
+        &format!("{POEM}:1\n{FROGS}:1\n"),
+    )
+}
+
+#[test]
+fn files_with_matches() -> TestResult {
+    run(&["-l", "-i", "FROGS", POEM, FROGS], &format!("{FROGS}\n"))
+}
+
+#[test]
+fn pattern_after_double_dash() -> TestResult {
+    run(&["--", "- don't", POEM], "Then there's a pair of us - don't tell!\n")
+}
+
+// --------------------------------------------------
+#[test]
+fn case_flag_wins_over_env() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .env("IGNORE_CASE", "1")
+        .args(["frogs", FROGS])
+        .assert()
+        .stdout("FROGS are green.\n");
+
+    Command::cargo_bin(PRG)?
+        .env("IGNORE_CASE", "1")
+        .args(["-s", "frogs", FROGS])
+        .assert()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn reads_stdin() -> TestResult {
+    for args in [&["-n", "frog"][..], &["-n", "frog", "-"][..]] {
+        Command::cargo_bin(PRG)?
+            .env_remove("IGNORE_CASE")
+            .args(args)
+            .write_stdin("toad\nfrog\n")
+            .assert()
+            .success()
+            .stdout("2:frog\n");
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exit_status() -> TestResult {
+    let cases: [(&[&str], i32); 7] = [
+        (&["frog", POEM], 0),
+        (&["toad", POEM], 1),
+        (&["frog", POEM, "tests/inputs/missing.txt"], 2),
+        (&["-q", "frog", POEM, "tests/inputs/missing.txt"], 0),
+        (&["-E", "(frog", POEM], 2),
+        (&["-r", "-t", "frogscript", "frog", "tests/inputs"], 2),
+        (&["--frobnicate", "frog", POEM], 2),
+    ];
+    for (args, code) in cases {
+        Command::cargo_bin(PRG)?
+            .env_remove("IGNORE_CASE")
+            .args(args)
+            .assert()
+            .code(code);
+    }
+    Ok(())
+}
+
+#[test]
+fn quiet_prints_nothing() -> TestResult {
+    run(&["-q", "frog", POEM, FROGS], "")?;
+    run(&["-q", "--json", "frog", POEM, FROGS], "")
+}
+
+#[test]
+fn files_without_match() -> TestResult {
+    run(&["-L", "toad", POEM, FROGS], &format!("{POEM}\n{FROGS}\n"))
+}
+
+#[test]
+fn max_count_and_only_matching() -> TestResult {
+    run(&["-o", "-m", "1", "-n", "nobody", POEM], "1:nobody\n")
+}
+
+#[test]
+fn replace_preview() -> TestResult {
+    run(
+        &["-n", "-E", "--replace", "$2 $1", r"(\w+) (are|sat)", FROGS],
+        "1:The sat frog on a log.\n2:are FROGS green.\n",
+    )
+}
+
+#[test]
+fn fuzzy_distance() -> TestResult {
+    run(
+        &["-n", "-i", "--fuzzy", "1", "frgs", FROGS],
+        "2:distance=1:FROGS are green.\n",
+    )?;
+    run(
+        &["-i", "--fuzzy", "2", "fraggs", FROGS],
+        "distance=2:The frog sat on a log.\ndistance=2:FROGS are green.\n",
+    )
+}
+
+#[test]
+fn search_zip() -> TestResult {
+    run(
+        &["-z", "-n", "green", FROGS_GZ, FROGS],
+        &format!("{FROGS_GZ}:2:FROGS are green.\n{FROGS}:2:FROGS are green.\n"),
+    )?;
+    //without -z the compressed file is binary and skipped
+    Command::cargo_bin(PRG)?
+        .args(["green", FROGS_GZ])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+#[test]
+fn interactive_needs_a_terminal() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--interactive", "frog", FROGS])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("needs a terminal"));
+    Ok(())
+}
+
+#[test]
+fn multiline() -> TestResult {
+    run(
+        &["-U", "-n", "-E", r"log\.\nFROGS", FROGS],
+        "1:The frog sat on a log.\n2:FROGS are green.\n",
+    )
+}